@@ -0,0 +1,358 @@
+use std::collections::HashSet;
+use std::f32::consts::PI;
+use bevy::prelude::*;
+use bevy_egui::{egui, EguiContexts};
+use bevy_prototype_lyon::prelude::*;
+
+use crate::drawing;
+use crate::grid::Grid;
+use crate::helper::{self, MainCamera, MyWorldCoords};
+use crate::history::Edit;
+use crate::model::{self, ElementKind, ParentSystem, SystemNode};
+use crate::placement::DEFAULT_SYSTEM_RADIUS;
+use crate::selection::{self, Carry, PlacedElements, Selected};
+use crate::toolbar_menu::{self, ActiveTool};
+
+/// Lines systems, sources and sinks up with each other.
+///
+/// While one is being placed or moved, dashed guides show where its centre or an edge lines
+/// up with another element's, and it snaps onto them. With several selected, a panel aligns
+/// their edges or centres, or spreads them out evenly.
+pub struct AlignPlugin;
+
+impl Plugin for AlignPlugin {
+        fn build(&self, app: &mut App) {
+                app.init_resource::<Guides>()
+                        .init_resource::<PlacementPoint>()
+                        .add_systems(Update, (
+                                (guide_placement.after(helper::update_cursor_position), draw_guides).chain(),
+                                align_panel,
+                        ));
+        }
+}
+
+/// How close (in screen pixels) an element has to come to lining up with another to snap to it.
+pub const GUIDE_TOLERANCE: f32 = 5.;
+
+/// Length of the dashes of the guides, in screen pixels.
+const GUIDE_DASH: f32 = 4.;
+
+const GUIDE_COLOR: Color = Color::rgb(0.95, 0.25, 0.45);
+
+/// Everything that could be lined up with, i.e. every element with a shape.
+pub type Alignables<'w, 's> = Query<'w, 's, (
+        Entity,
+        &'static ElementKind,
+        &'static Path,
+        &'static GlobalTransform,
+        &'static InheritedVisibility,
+)>;
+
+/// The elements that get guides: the ones that stand on their own on the canvas.
+pub fn is_alignable(kind: ElementKind) -> bool {
+        matches!(kind, ElementKind::System | ElementKind::Source | ElementKind::Sink)
+}
+
+/// The world bounds of every visible alignable element, except those `skip` says not to.
+pub fn alignable_bounds(q_alignables: &Alignables, skip: impl Fn(Entity) -> bool) -> Vec<Rect> {
+        q_alignables
+                .iter()
+                .filter(|(entity, kind, .., visibility)| is_alignable(**kind) && visibility.get() && !skip(*entity))
+                .filter_map(|(_, _, path, transform, _)| selection::world_bounds(path, transform))
+                .collect()
+}
+
+/// The guides to draw this frame. Whoever is placing or moving something adds them, and
+/// they're gone once drawn.
+#[derive(Resource, Debug, Default)]
+pub struct Guides {
+        lines: Vec<(Vec2, Vec2)>,
+}
+
+/// Where clicking with the active tool puts the new element: on the cursor, or snapped to the
+/// grid and the alignment guides when they're on.
+#[derive(Resource, Debug, Default)]
+pub struct PlacementPoint(pub Vec2);
+
+// the left edge, centre and right edge of a rect; or its bottom, middle and top
+fn vertical_lines(rect: Rect) -> [f32; 3] {
+        [rect.min.x, rect.center().x, rect.max.x]
+}
+
+fn horizontal_lines(rect: Rect) -> [f32; 3] {
+        [rect.min.y, rect.center().y, rect.max.y]
+}
+
+/// The smallest shift, no bigger than `tolerance`, that lines one of `moving`'s lines up with
+/// one of another rect's.
+fn nearest_shift(moving: Rect, others: &[Rect], tolerance: f32, lines: fn(Rect) -> [f32; 3]) -> f32 {
+        let mut nearest: Option<f32> = None;
+        for other in others {
+                for a in lines(moving) {
+                        for b in lines(*other) {
+                                let shift = b - a;
+                                if shift.abs() <= tolerance && nearest.is_none_or(|n| shift.abs() < n.abs()) {
+                                        nearest = Some(shift);
+                                }
+                        }
+                }
+        }
+        nearest.unwrap_or(0.)
+}
+
+impl Guides {
+        /// Shifts `moving` by at most `tolerance` to line it up with the `others`, and adds the
+        /// guides it then lines up along. Returns the shift.
+        pub fn snap(&mut self, moving: Rect, others: &[Rect], tolerance: f32) -> Vec2 {
+                let shift = Vec2::new(
+                        nearest_shift(moving, others, tolerance, vertical_lines),
+                        nearest_shift(moving, others, tolerance, horizontal_lines),
+                );
+                let moved = Rect::from_corners(moving.min + shift, moving.max + shift);
+
+                // a guide runs across both rects
+                const LINED_UP: f32 = 0.01;
+                for other in others {
+                        let (bottom, top) = (moved.min.y.min(other.min.y), moved.max.y.max(other.max.y));
+                        for x in vertical_lines(moved) {
+                                if vertical_lines(*other).iter().any(|b| (b - x).abs() < LINED_UP) {
+                                        self.lines.push((Vec2::new(x, bottom), Vec2::new(x, top)));
+                                }
+                        }
+                        let (left, right) = (moved.min.x.min(other.min.x), moved.max.x.max(other.max.x));
+                        for y in horizontal_lines(moved) {
+                                if horizontal_lines(*other).iter().any(|b| (b - y).abs() < LINED_UP) {
+                                        self.lines.push((Vec2::new(left, y), Vec2::new(right, y)));
+                                }
+                        }
+                }
+                shift
+        }
+}
+
+/// Roughly where an element of `kind` placed at `point` would be: the size it's spawned at,
+/// and facing the way it does with no system around.
+fn placed_bounds(kind: ElementKind, point: Vec2) -> Option<Rect> {
+        let (path, transform) = match kind {
+                ElementKind::System => (drawing::system_path(DEFAULT_SYSTEM_RADIUS), Transform::from_translation(point.extend(0.))),
+                ElementKind::Sink   => (drawing::sink_path(), drawing::sink_transform(point, PI)),
+                ElementKind::Source => (drawing::source_path(), drawing::source_transform(point, 0.)),
+                _ => return None,
+        };
+        selection::world_bounds(&path, &GlobalTransform::from(transform))
+}
+
+/// Works out where a click would place the element, showing the guides it lines up along.
+#[allow(clippy::too_many_arguments)]
+pub fn guide_placement(
+        mut contexts: EguiContexts,
+        active_tool: Res<State<ActiveTool>>,
+        cursor: Res<MyWorldCoords>,
+        grid: Res<Grid>,
+        mut guides: ResMut<Guides>,
+        mut point: ResMut<PlacementPoint>,
+        q_camera: Query<&OrthographicProjection, With<MainCamera>>,
+        q_alignables: Alignables,
+) {
+        point.0 = match *active_tool.get() {
+                // flows are drawn between elements, so they go where the cursor points
+                ActiveTool::Select | ActiveTool::Place(ElementKind::Flow) => cursor.0,
+                ActiveTool::Place(kind) if is_alignable(kind) && !contexts.ctx_mut().is_pointer_over_area() => {
+                        let snapped = grid.snap(cursor.0);
+                        let scale = q_camera.get_single().map_or(1., |projection| projection.scale);
+                        let others = alignable_bounds(&q_alignables, |_| false);
+                        let placed = placed_bounds(kind, snapped).unwrap_or(Rect::from_center_size(snapped, Vec2::ZERO));
+                        snapped + guides.snap(placed, &others, GUIDE_TOLERANCE * scale)
+                }
+                ActiveTool::Place(_) => grid.snap(cursor.0),
+        };
+}
+
+fn draw_guides(
+        mut gizmos: Gizmos,
+        mut guides: ResMut<Guides>,
+        q_camera: Query<&OrthographicProjection, With<MainCamera>>,
+) {
+        if guides.lines.is_empty() {
+                return;
+        }
+        let scale = q_camera.get_single().map_or(1., |projection| projection.scale);
+        for (from, to) in guides.lines.drain(..) {
+                drawing::dashed_line(&mut gizmos, from, to, GUIDE_DASH * scale, GUIDE_COLOR);
+        }
+}
+
+/// The ways a multi-selection can be lined up.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Alignment {
+        Left,
+        Centre,
+        Right,
+        Top,
+        Middle,
+        Bottom,
+        DistributeHorizontally,
+        DistributeVertically,
+}
+
+impl Alignment {
+        const ROWS: [[Alignment; 4]; 2] = [
+                [Alignment::Left, Alignment::Centre, Alignment::Right, Alignment::DistributeHorizontally],
+                [Alignment::Top, Alignment::Middle, Alignment::Bottom, Alignment::DistributeVertically],
+        ];
+
+        fn label(&self) -> &'static str {
+                match self {
+                        Alignment::Left                   => "Left",
+                        Alignment::Centre                 => "Centre",
+                        Alignment::Right                  => "Right",
+                        Alignment::Top                    => "Top",
+                        Alignment::Middle                 => "Middle",
+                        Alignment::Bottom                 => "Bottom",
+                        Alignment::DistributeHorizontally => "Spread ↔",
+                        Alignment::DistributeVertically   => "Spread ↕",
+                }
+        }
+
+        fn edit(&self) -> &'static str {
+                match self {
+                        Alignment::Left                   => "Align left",
+                        Alignment::Centre                 => "Align centres",
+                        Alignment::Right                  => "Align right",
+                        Alignment::Top                    => "Align top",
+                        Alignment::Middle                 => "Align middles",
+                        Alignment::Bottom                 => "Align bottom",
+                        Alignment::DistributeHorizontally => "Distribute horizontally",
+                        Alignment::DistributeVertically   => "Distribute vertically",
+                }
+        }
+
+        /// Spreading out only does something with at least three elements.
+        fn min_elements(&self) -> usize {
+                match self {
+                        Alignment::DistributeHorizontally | Alignment::DistributeVertically => 3,
+                        _ => 2,
+                }
+        }
+
+        /// How far each of the elements (given by their bounds) has to move.
+        fn shifts(&self, items: &[(Entity, Rect)]) -> Vec<(Entity, Vec2)> {
+                let left   = items.iter().map(|(_, r)| r.min.x).fold(f32::INFINITY, f32::min);
+                let right  = items.iter().map(|(_, r)| r.max.x).fold(f32::NEG_INFINITY, f32::max);
+                let bottom = items.iter().map(|(_, r)| r.min.y).fold(f32::INFINITY, f32::min);
+                let top    = items.iter().map(|(_, r)| r.max.y).fold(f32::NEG_INFINITY, f32::max);
+                match self {
+                        Alignment::Left   => items.iter().map(|(e, r)| (*e, Vec2::new(left - r.min.x, 0.))).collect(),
+                        Alignment::Right  => items.iter().map(|(e, r)| (*e, Vec2::new(right - r.max.x, 0.))).collect(),
+                        Alignment::Centre => {
+                                let centre = (left + right) / 2.;
+                                items.iter().map(|(e, r)| (*e, Vec2::new(centre - r.center().x, 0.))).collect()
+                        }
+                        Alignment::Top    => items.iter().map(|(e, r)| (*e, Vec2::new(0., top - r.max.y))).collect(),
+                        Alignment::Bottom => items.iter().map(|(e, r)| (*e, Vec2::new(0., bottom - r.min.y))).collect(),
+                        Alignment::Middle => {
+                                let middle = (bottom + top) / 2.;
+                                items.iter().map(|(e, r)| (*e, Vec2::new(0., middle - r.center().y))).collect()
+                        }
+                        // equal gaps between neighbours, with the outermost two staying put
+                        Alignment::DistributeHorizontally => distribute(items, |r| r.min.x, |r| r.width(), Vec2::X),
+                        Alignment::DistributeVertically => distribute(items, |r| r.min.y, |r| r.height(), Vec2::Y),
+                }
+        }
+}
+
+/// Shifts along `axis` that leave equal gaps between the elements, in the order they're in.
+fn distribute(items: &[(Entity, Rect)], start: fn(&Rect) -> f32, size: fn(&Rect) -> f32, axis: Vec2) -> Vec<(Entity, Vec2)> {
+        let mut sorted: Vec<&(Entity, Rect)> = items.iter().collect();
+        sorted.sort_by(|(_, a), (_, b)| (start(a) + size(a) / 2.).total_cmp(&(start(b) + size(b) / 2.)));
+        let (Some((_, first)), Some((_, last))) = (sorted.first(), sorted.last()) else {
+                return Vec::new();
+        };
+        let span = start(last) + size(last) - start(first);
+        let taken: f32 = sorted.iter().map(|(_, r)| size(r)).sum();
+        let gap = (span - taken) / (sorted.len() - 1).max(1) as f32;
+
+        let mut at = start(first);
+        sorted
+                .into_iter()
+                .map(|(entity, rect)| {
+                        let shift = axis * (at - start(rect));
+                        at += size(rect) + gap;
+                        (*entity, shift)
+                })
+                .collect()
+}
+
+/// Offers to line up the selected systems, sources and sinks, when there are several.
+#[allow(clippy::too_many_arguments)]
+fn align_panel(
+        mut contexts: EguiContexts,
+        mut edits: EventWriter<Edit>,
+        q_selected: Query<Entity, With<Selected>>,
+        q_kinds: Query<(Entity, &ElementKind)>,
+        q_alignables: Alignables,
+        q_parents: Query<&ParentSystem>,
+        q_systems: Query<(Entity, &SystemNode)>,
+        mut q_transforms: PlacedElements,
+) {
+        // whatever is in a selected system goes along with it
+        let selected: HashSet<Entity> = q_selected.iter().collect();
+        let carried = |system: Entity| {
+                selected.contains(&system) || model::ancestors(system, &q_parents).iter().any(|a| selected.contains(a))
+        };
+        let carry = Carry::new(
+                q_systems.iter().map(|(system, node)| (system, node.radius)),
+                q_kinds.iter().map(|(entity, kind)| (entity, *kind)),
+                &q_transforms,
+        );
+        let carried_along = carry.loose_in(carried);
+        let items: Vec<(Entity, Rect)> = q_alignables
+                .iter()
+                .filter(|(entity, kind, ..)| is_alignable(**kind) && selected.contains(entity))
+                .filter(|(entity, ..)| !model::ancestors(*entity, &q_parents).iter().any(|a| selected.contains(a)))
+                .filter(|(entity, ..)| !carried_along.contains(entity))
+                .filter_map(|(entity, _, path, transform, _)| Some((entity, selection::world_bounds(path, transform)?)))
+                .collect();
+        if items.len() < 2 {
+                return;
+        }
+
+        let mut picked = None;
+        egui::Window::new("Align")
+                .title_bar(false)
+                .movable(false)
+                .resizable(false)
+                .anchor(egui::Align2::RIGHT_TOP, egui::Vec2::ZERO)
+                .frame(toolbar_menu::floating_frame())
+                .show(contexts.ctx_mut(), |ui| {
+                        ui.strong(format!("Align {} elements", items.len()));
+                        for row in Alignment::ROWS {
+                                ui.horizontal(|ui| {
+                                        for alignment in row {
+                                                let enabled = items.len() >= alignment.min_elements();
+                                                if ui.add_enabled(enabled, egui::Button::new(alignment.label())).clicked() {
+                                                        picked = Some(alignment);
+                                                }
+                                        }
+                                });
+                        }
+                });
+        let Some(alignment) = picked else {
+                return;
+        };
+
+        let mut moved = false;
+        for (entity, shift) in alignment.shifts(&items) {
+                if q_systems.contains(entity) {
+                        moved |= carry.move_system(entity, shift, &q_parents, &mut q_transforms);
+                } else if let Ok(mut transform) = q_transforms.get_mut(entity) {
+                        if shift != Vec2::ZERO {
+                                transform.translation += shift.extend(0.);
+                                moved = true;
+                        }
+                }
+        }
+        if moved {
+                edits.send(Edit(alignment.edit().to_string()));
+        }
+}
@@ -0,0 +1,310 @@
+use std::collections::BTreeMap;
+use std::fmt::Write;
+use std::path::Path;
+use bevy::prelude::*;
+use bevy_egui::EguiContexts;
+
+use crate::document::DocumentPath;
+use crate::model::{ElementId, ElementKind, FlowQuantity};
+use crate::selection::Selected;
+use crate::simulation::{self, FlowState, Simulation, Stock};
+use crate::toolbar_menu;
+
+/// Records how the simulation goes, and charts it.
+///
+/// Every stock and flow rate is sampled as the simulation runs. A panel charts the stock of
+/// the selected system (or sink) and the rate of the selected flow over simulated time, and
+/// exports everything recorded to a CSV file next to the document.
+pub struct ChartsPlugin;
+
+impl Plugin for ChartsPlugin {
+        fn build(&self, app: &mut App) {
+                app.init_resource::<Recording>()
+                        .add_systems(FixedUpdate, record_simulation.after(simulation::step_simulation))
+                        .add_systems(Update, chart_panel);
+        }
+}
+
+/// Simulated seconds between samples, to begin with.
+const SAMPLE_INTERVAL: f32 = 0.1;
+/// Most samples kept (an hour of simulated time at first). Once there are this many, every
+/// other one is dropped and samples are taken half as often, so long runs are recorded
+/// from start to end, just more coarsely.
+const MAX_SAMPLES: usize = 36_000;
+
+const CHART_SIZE: egui::Vec2 = egui::vec2(300., 110.);
+const STOCK_COLOR: egui::Color32 = egui::Color32::from_rgb(30, 110, 60);
+const RATE_COLOR: egui::Color32 = egui::Color32::from_rgb(30, 80, 200);
+
+/// Samples of every stock and flow rate since the simulation last started.
+#[derive(Resource)]
+struct Recording {
+        /// Simulated seconds between samples; grows as the recording is thinned out.
+        interval: f32,
+        /// When each sample was taken.
+        times: Vec<f32>,
+        /// Keyed by element, and then by what was measured.
+        series: BTreeMap<(u64, Measure), Series>,
+}
+
+impl Default for Recording {
+        fn default() -> Self {
+                Self { interval: SAMPLE_INTERVAL, times: Vec::new(), series: BTreeMap::new() }
+        }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+enum Measure {
+        Stock,
+        Rate,
+}
+
+struct Series {
+        name: String,
+        unit: String,
+        /// One per entry of `Recording::times` so far, with nothing where the element wasn't
+        /// around: before it was added, or while it was deleted (until that was undone).
+        values: Vec<Option<f32>>,
+}
+
+impl Series {
+        /// Value at sample `i`, if the element was around then.
+        fn at(&self, i: usize) -> Option<f32> {
+                self.values.get(i).copied().flatten()
+        }
+}
+
+impl Recording {
+        fn record(&mut self, key: (u64, Measure), name: &str, unit: &str, value: f32) {
+                let series = self.series.entry(key).or_insert_with(|| Series {
+                        name: String::new(),
+                        unit: String::new(),
+                        values: Vec::new(),
+                });
+                // keep up with renames
+                if series.name != name {
+                        series.name = name.to_string();
+                }
+                if series.unit != unit {
+                        series.unit = unit.to_string();
+                }
+                // leave a gap for the samples it missed
+                series.values.resize(self.times.len() - 1, None);
+                series.values.push(Some(value));
+        }
+
+        /// Keeps every other sample, to make room for as many again taken twice as far apart.
+        fn thin_out(&mut self) {
+                self.times = self.times.iter().copied().step_by(2).collect();
+                for series in self.series.values_mut() {
+                        series.values = series.values.iter().copied().step_by(2).collect();
+                }
+                self.interval *= 2.;
+        }
+
+        /// Writes every series as a column, one row per sample.
+        fn to_csv(&self) -> String {
+                let escape = |text: String| {
+                        if text.contains([',', '"', '\n']) { format!("\"{}\"", text.replace('"', "\"\"")) } else { text }
+                };
+                let mut csv = String::from("time (s)");
+                for ((_, measure), series) in &self.series {
+                        let heading = match (measure, series.unit.is_empty()) {
+                                (Measure::Stock, _)    => format!("{} stock", series.name),
+                                (Measure::Rate, true)  => format!("{} rate", series.name),
+                                (Measure::Rate, false) => format!("{} rate ({})", series.name, series.unit),
+                        };
+                        csv.push(',');
+                        csv.push_str(&escape(heading));
+                }
+                csv.push('\n');
+                for (i, time) in self.times.iter().enumerate() {
+                        let _ = write!(csv, "{time}");
+                        for series in self.series.values() {
+                                csv.push(',');
+                                if let Some(value) = series.at(i) {
+                                        let _ = write!(csv, "{value}");
+                                }
+                        }
+                        csv.push('\n');
+                }
+                csv
+        }
+
+        fn export_csv(&self, path: &Path) -> std::io::Result<()> {
+                std::fs::write(path, self.to_csv())
+        }
+}
+
+fn record_simulation(
+        simulation: Res<Simulation>,
+        mut recording: ResMut<Recording>,
+        q_stocks: Query<(&ElementId, &Name, &Stock)>,
+        q_flows: Query<(&ElementId, &Name, &FlowState, &FlowQuantity)>,
+) {
+        // the simulation went back to the start
+        if recording.times.last().is_some_and(|&last| simulation.time < last) {
+                *recording = Recording::default();
+        }
+        let due = recording.times.last().map_or(simulation.time > 0., |&last| simulation.time >= last + recording.interval);
+        if !due {
+                return;
+        }
+        if recording.times.len() >= MAX_SAMPLES {
+                recording.thin_out();
+        }
+
+        recording.times.push(simulation.time);
+        for (id, name, stock) in &q_stocks {
+                recording.record((id.0, Measure::Stock), name, "", stock.0);
+        }
+        for (id, name, state, quantity) in &q_flows {
+                recording.record((id.0, Measure::Rate), name, &quantity.unit, state.rate);
+        }
+}
+
+/// Charts the selected stock and flow rate, and offers to export the recording.
+fn chart_panel(
+        mut contexts: EguiContexts,
+        recording: Res<Recording>,
+        document_path: Res<DocumentPath>,
+        q_selected: Query<(&ElementId, &ElementKind), With<Selected>>,
+) {
+        if recording.times.is_empty() {
+                return;
+        }
+        let mut selected: Vec<(ElementId, ElementKind)> = q_selected.iter().map(|(id, kind)| (*id, *kind)).collect();
+        selected.sort_by_key(|(id, _)| *id);
+        let first = |measure: Measure| {
+                selected.iter().find_map(|(id, kind)| {
+                        let measured = match measure {
+                                Measure::Stock => matches!(kind, ElementKind::System | ElementKind::Sink),
+                                Measure::Rate  => *kind == ElementKind::Flow,
+                        };
+                        measured.then(|| recording.series.get(&(id.0, measure))).flatten()
+                })
+        };
+        let (stock, rate) = (first(Measure::Stock), first(Measure::Rate));
+        if stock.is_none() && rate.is_none() {
+                return;
+        }
+
+        egui::Window::new("Chart")
+                .title_bar(false)
+                .movable(false)
+                .resizable(false)
+                .anchor(egui::Align2::RIGHT_CENTER, egui::Vec2::ZERO)
+                .frame(toolbar_menu::floating_frame())
+                .show(contexts.ctx_mut(), |ui| {
+                        if let Some(series) = stock {
+                                ui.strong(format!("{} stock", series.name));
+                                chart(ui, &recording.times, series, STOCK_COLOR);
+                        }
+                        if let Some(series) = rate {
+                                ui.strong(format!("{} rate ({})", series.name, series.unit));
+                                chart(ui, &recording.times, series, RATE_COLOR);
+                        }
+                        if recording.interval > SAMPLE_INTERVAL {
+                                ui.weak(format!("Sampled every {:.1} s to fit the whole run", recording.interval));
+                        }
+                        ui.separator();
+                        if ui.button("Export CSV").clicked() {
+                                let path = document_path.0.with_extension("csv");
+                                match recording.export_csv(&path) {
+                                        Ok(())  => info!("exported simulation results to {}", path.display()),
+                                        Err(e) => error!("could not export {}: {e}", path.display()),
+                                }
+                        }
+                });
+}
+
+/// Plots `series` against the times it was sampled at, from zero up to its largest value.
+fn chart(ui: &mut egui::Ui, times: &[f32], series: &Series, color: egui::Color32) {
+        let (rect, _) = ui.allocate_exact_size(CHART_SIZE, egui::Sense::hover());
+        let painter = ui.painter_at(rect);
+        painter.rect(rect, 2., egui::Color32::from_gray(248), egui::Stroke::new(1., egui::Color32::from_gray(200)));
+
+        let end = times.last().copied().unwrap_or(0.).max(SAMPLE_INTERVAL);
+        let top = series.values.iter().flatten().copied().fold(0., f32::max).max(f32::EPSILON);
+        // no point drawing more than a point per pixel
+        let stride = (series.values.len() / rect.width() as usize).max(1);
+        let points = series.values
+                .iter()
+                .zip(times)
+                .step_by(stride)
+                .map(|(value, time)| value.map(|value| egui::pos2(
+                        rect.left() + rect.width() * time / end,
+                        rect.bottom() - rect.height() * value / top,
+                )));
+        // a line for each stretch the element was around for
+        let mut line = Vec::new();
+        for point in points.chain([None]) {
+                match point {
+                        Some(point) => line.push(point),
+                        None if !line.is_empty() => {
+                                painter.add(egui::Shape::line(std::mem::take(&mut line), egui::Stroke::new(1.5, color)));
+                        }
+                        None => {}
+                }
+        }
+
+        let font = egui::FontId::monospace(10.);
+        let text_color = egui::Color32::DARK_GRAY;
+        painter.text(rect.left_top() + egui::vec2(3., 2.), egui::Align2::LEFT_TOP, format!("{top:.1}"), font.clone(), text_color);
+        painter.text(rect.left_bottom() + egui::vec2(3., -2.), egui::Align2::LEFT_BOTTOM, "0", font.clone(), text_color);
+        painter.text(rect.right_bottom() + egui::vec2(-3., -2.), egui::Align2::RIGHT_BOTTOM, format!("{end:.1} s"), font.clone(), text_color);
+        if let Some(last) = series.at(times.len().saturating_sub(1)) {
+                painter.text(rect.right_top() + egui::vec2(-3., 2.), egui::Align2::RIGHT_TOP, format!("now {last:.2}"), font, color);
+        }
+}
+
+#[cfg(test)]
+mod tests {
+        use super::*;
+
+        #[test]
+        fn thinning_out_keeps_series_in_step() {
+                let mut recording = Recording::default();
+                for i in 0..6 {
+                        recording.times.push(i as f32);
+                        recording.record((1, Measure::Stock), "pond", "", i as f32);
+                        if i >= 3 {
+                                recording.record((2, Measure::Rate), "rain", "l", 10. * i as f32);
+                        }
+                }
+                recording.thin_out();
+
+                assert_eq!(recording.times, [0., 2., 4.]);
+                assert_eq!(recording.interval, 2. * SAMPLE_INTERVAL);
+                let pond = &recording.series[&(1, Measure::Stock)];
+                let rain = &recording.series[&(2, Measure::Rate)];
+                assert_eq!((0..3).map(|i| pond.at(i)).collect::<Vec<_>>(), [Some(0.), Some(2.), Some(4.)]);
+                assert_eq!((0..3).map(|i| rain.at(i)).collect::<Vec<_>>(), [None, None, Some(40.)]);
+
+                // later samples line up with the times kept
+                recording.times.push(6.);
+                recording.record((2, Measure::Rate), "rain", "l", 60.);
+                assert_eq!(recording.series[&(2, Measure::Rate)].at(3), Some(60.));
+        }
+
+        #[test]
+        fn gaps_where_an_element_was_gone() {
+                let mut recording = Recording::default();
+                for i in 0..6 {
+                        recording.times.push(i as f32);
+                        // deleted after sample 1, and back (undone) at sample 4
+                        if !(2..4).contains(&i) {
+                                recording.record((1, Measure::Stock), "pond", "", i as f32);
+                        }
+                }
+
+                let pond = &recording.series[&(1, Measure::Stock)];
+                let values: Vec<_> = (0..6).map(|i| pond.at(i)).collect();
+                assert_eq!(values, [Some(0.), Some(1.), None, None, Some(4.), Some(5.)]);
+                assert_eq!(recording.to_csv().lines().skip(1).collect::<Vec<_>>(), ["0,0", "1,1", "2,", "3,", "4,4", "5,5"]);
+
+                recording.thin_out();
+                let pond = &recording.series[&(1, Measure::Stock)];
+                assert_eq!((0..3).map(|i| pond.at(i)).collect::<Vec<_>>(), [Some(0.), None, Some(4.)]);
+        }
+}
@@ -0,0 +1,261 @@
+use bevy::prelude::*;
+use bevy_egui::EguiContexts;
+
+use crate::drawing;
+use crate::flow;
+use crate::helper::MainCamera;
+use crate::history::Edit;
+use crate::minimap;
+use crate::model::{Disruption, DisruptionSchedule, ElementId, ElementKind, FlowCurve, Perturbation, SystemNode};
+use crate::selection::Selected;
+use crate::simulation::{self, DisruptionState, Simulation};
+use crate::toolbar_menu;
+
+/// Shows what disruptions are attached to and when they strike.
+///
+/// Each disruption is tied to its target by a dashed line, and glows while it's in effect.
+/// The inspector sets what a disruption does and when, and a timeline lays out every
+/// disruption's schedule (or, for random ones, when they've struck so far) against the
+/// simulation clock.
+pub struct DisruptionPlugin;
+
+impl Plugin for DisruptionPlugin {
+        fn build(&self, app: &mut App) {
+                app.add_systems(Update, (disruption_timeline, draw_disruption_links));
+        }
+}
+
+const LINK_COLOR: Color = Color::rgb(0.55, 0.45, 0.);
+const ACTIVE_COLOR: Color = Color::rgb(0.9, 0.15, 0.1);
+/// Length of the dashes tying disruptions to their targets, on screen in pixels.
+const LINK_DASH: f32 = 6.;
+/// Radius of the glow around a disruption in effect.
+const GLOW_RADIUS: f32 = 50.;
+
+const TIMELINE_WIDTH: f32 = 320.;
+const TIMELINE_ROW_HEIGHT: f32 = 14.;
+/// The timeline shows at least this many simulated seconds.
+const MIN_TIMELINE_SPAN: f32 = 60.;
+
+// what a perturbation starts out as when it's picked in the panel
+const DEFAULT_REDUCTION: f32 = 0.5;
+const DEFAULT_SPIKE: f32 = 3.;
+const PERTURBATIONS: [Perturbation; 3] = [
+        Perturbation::CutFlow,
+        Perturbation::ReduceCapacity { to: DEFAULT_REDUCTION },
+        Perturbation::SpikeInput { by: DEFAULT_SPIKE },
+];
+
+/// Anything a disruption can be attached to, apart from flows.
+type Targets<'w, 's> = Query<'w, 's, (&'static ElementKind, &'static Transform, Option<&'static SystemNode>)>;
+
+fn draw_disruption_links(
+        mut gizmos: Gizmos,
+        simulation: Res<Simulation>,
+        q_camera: Query<&OrthographicProjection, With<MainCamera>>,
+        q_disruptions: Query<(&Disruption, Option<&DisruptionState>, &Transform, &InheritedVisibility)>,
+        q_curves: Query<&FlowCurve>,
+        q_targets: Targets,
+) {
+        let scale = q_camera.get_single().map_or(1., |projection| projection.scale);
+        for (disruption, state, transform, visibility) in &q_disruptions {
+                if !visibility.get() {
+                        continue;
+                }
+                let position = transform.translation.xy();
+                let active = state.is_some_and(|state| simulation::in_effect(disruption, state, simulation.time));
+                let color = if active { ACTIVE_COLOR } else { LINK_COLOR };
+                if active {
+                        gizmos.circle_2d(position, GLOW_RADIUS, ACTIVE_COLOR);
+                }
+
+                let Some(target) = disruption.target else { continue };
+                let point = match (q_curves.get(target), q_targets.get(target)) {
+                        (Ok(curve), _) => curve.point_at(0.5),
+                        (_, Ok((kind, transform, system))) => flow::anchor(*kind, transform, system, position),
+                        _ => continue,
+                };
+                drawing::dashed_line(&mut gizmos, position, point, LINK_DASH * scale, color);
+        }
+}
+
+/// Sets what a disruption does and when; `target_name` names what it's attached to.
+pub fn disruption_properties(
+        ui: &mut egui::Ui,
+        edits: &mut EventWriter<Edit>,
+        mut disruption: Mut<Disruption>,
+        target_name: impl Fn(Entity) -> String,
+) {
+        let mut edited = *disruption;
+        // picking something from a list is done at once; dragging or typing a number once let go
+        let (mut picked, mut finished) = (false, false);
+        let mut track = |response: egui::Response| {
+                finished |= response.drag_released() || response.lost_focus();
+        };
+
+        egui::Grid::new("disruption").num_columns(2).show(ui, |ui| {
+                ui.label("Attached to");
+                ui.horizontal(|ui| {
+                        match edited.target {
+                                Some(target) => {
+                                        ui.label(target_name(target));
+                                        if ui.small_button("Detach").clicked() {
+                                                edited.target = None;
+                                                picked = true;
+                                        }
+                                }
+                                None => {
+                                        ui.weak("nothing");
+                                }
+                        }
+                });
+                ui.end_row();
+
+                ui.label("Effect");
+                egui::ComboBox::from_id_source("disruption perturbation")
+                        .selected_text(edited.perturbation.label())
+                        .show_ui(ui, |ui| {
+                                for perturbation in PERTURBATIONS {
+                                        let current = std::mem::discriminant(&edited.perturbation) == std::mem::discriminant(&perturbation);
+                                        if ui.selectable_label(current, perturbation.label()).clicked() && !current {
+                                                edited.perturbation = perturbation;
+                                                picked = true;
+                                        }
+                                }
+                        });
+                ui.end_row();
+
+                match &mut edited.perturbation {
+                        Perturbation::CutFlow => {}
+                        Perturbation::ReduceCapacity { to } => {
+                                ui.label("Let through");
+                                track(ui.add(egui::DragValue::new(to).clamp_range(0.0..=1.0).speed(0.01)));
+                                ui.end_row();
+                        }
+                        Perturbation::SpikeInput { by } => {
+                                ui.label("Input ×");
+                                track(ui.add(egui::DragValue::new(by).clamp_range(0.0..=f32::MAX).speed(0.1)));
+                                ui.end_row();
+                        }
+                }
+
+                ui.label("When");
+                ui.horizontal(|ui| {
+                        let duration = match edited.schedule {
+                                DisruptionSchedule::At { duration, .. } | DisruptionSchedule::Random { duration, .. } => duration,
+                        };
+                        let scheduled = matches!(edited.schedule, DisruptionSchedule::At { .. });
+                        if ui.radio(scheduled, "Scheduled").clicked() && !scheduled {
+                                edited.schedule = DisruptionSchedule::At { start: 5., duration };
+                                picked = true;
+                        }
+                        if ui.radio(!scheduled, "Random").clicked() && scheduled {
+                                edited.schedule = DisruptionSchedule::Random { rate: 0.05, duration };
+                                picked = true;
+                        }
+                });
+                ui.end_row();
+
+                match &mut edited.schedule {
+                        DisruptionSchedule::At { start, duration } => {
+                                ui.label("Start");
+                                track(ui.add(egui::DragValue::new(start).clamp_range(0.0..=f32::MAX).speed(0.1).suffix(" s")));
+                                ui.end_row();
+                                ui.label("Duration");
+                                track(ui.add(egui::DragValue::new(duration).clamp_range(0.0..=f32::MAX).speed(0.1).suffix(" s")));
+                                ui.end_row();
+                        }
+                        DisruptionSchedule::Random { rate, duration } => {
+                                ui.label("Rate");
+                                track(ui.add(egui::DragValue::new(rate).clamp_range(0.0..=f32::MAX).speed(0.01).suffix(" /s")));
+                                ui.end_row();
+                                ui.label("Duration");
+                                track(ui.add(egui::DragValue::new(duration).clamp_range(0.0..=f32::MAX).speed(0.1).suffix(" s")));
+                                ui.end_row();
+                        }
+                }
+        });
+
+        disruption.set_if_neq(edited);
+        if picked || finished {
+                edits.send(Edit("Change disruption".to_string()));
+        }
+}
+
+/// The colour a perturbation is shown in on the timeline.
+fn timeline_color(perturbation: &Perturbation) -> egui::Color32 {
+        match perturbation {
+                Perturbation::CutFlow               => egui::Color32::from_rgb(200, 40, 30),
+                Perturbation::ReduceCapacity { .. } => egui::Color32::from_rgb(230, 150, 20),
+                Perturbation::SpikeInput { .. }     => egui::Color32::from_rgb(60, 110, 210),
+        }
+}
+
+/// Lists every disruption with a bar for when it's in effect; clicking one's name selects it.
+fn disruption_timeline(
+        mut commands: Commands,
+        mut contexts: EguiContexts,
+        simulation: Res<Simulation>,
+        q_disruptions: Query<(Entity, &ElementId, &Name, &Disruption, Option<&DisruptionState>)>,
+        q_selected: Query<Entity, With<Selected>>,
+) {
+        let mut disruptions: Vec<_> = q_disruptions.iter().collect();
+        if disruptions.is_empty() {
+                return;
+        }
+        disruptions.sort_by_key(|(_, id, ..)| **id);
+
+        let spans = |disruption: &Disruption, state: Option<&DisruptionState>| -> Vec<(f32, f32)> {
+                match disruption.schedule {
+                        DisruptionSchedule::At { start, duration } => vec![(start, start + duration)],
+                        DisruptionSchedule::Random { .. } => state.map_or(Vec::new(), |state| state.strikes.clone()),
+                }
+        };
+        let span = disruptions
+                .iter()
+                .flat_map(|(.., disruption, state)| spans(disruption, *state))
+                .map(|(_, end)| end)
+                .fold(MIN_TIMELINE_SPAN.max(simulation.time * 1.2), f32::max);
+
+        egui::Window::new("Timeline")
+                .title_bar(false)
+                .movable(false)
+                .resizable(false)
+                // above the minimap
+                .anchor(egui::Align2::LEFT_BOTTOM, egui::vec2(0., -minimap::HEIGHT))
+                .frame(toolbar_menu::floating_frame())
+                .show(contexts.ctx_mut(), |ui| {
+                        ui.strong("Disruptions");
+                        egui::Grid::new("disruption timeline").num_columns(2).show(ui, |ui| {
+                                for (entity, _, name, disruption, state) in &disruptions {
+                                        if ui.selectable_label(q_selected.contains(*entity), name.as_str()).clicked() {
+                                                for selected in q_selected.iter().filter(|selected| selected != entity) {
+                                                        commands.entity(selected).remove::<Selected>();
+                                                }
+                                                commands.entity(*entity).insert(Selected);
+                                        }
+
+                                        let (rect, _) = ui.allocate_exact_size(egui::vec2(TIMELINE_WIDTH, TIMELINE_ROW_HEIGHT), egui::Sense::hover());
+                                        let x = |time: f32| rect.left() + rect.width() * (time / span).clamp(0., 1.);
+                                        let painter = ui.painter();
+                                        painter.rect_filled(rect, 2., egui::Color32::from_gray(230));
+                                        for (start, end) in spans(disruption, *state) {
+                                                let bar = egui::Rect::from_x_y_ranges(x(start)..=x(end).max(x(start) + 1.), rect.y_range());
+                                                painter.rect_filled(bar, 2., timeline_color(&disruption.perturbation));
+                                        }
+                                        painter.vline(x(simulation.time), rect.y_range(), egui::Stroke::new(1.5, egui::Color32::BLACK));
+                                        ui.end_row();
+                                }
+
+                                ui.label("");
+                                ui.horizontal(|ui| {
+                                        ui.set_width(TIMELINE_WIDTH);
+                                        ui.weak("0 s");
+                                        ui.with_layout(egui::Layout::right_to_left(egui::Align::Center), |ui| {
+                                                ui.weak(format!("{span:.0} s"));
+                                        });
+                                });
+                                ui.end_row();
+                        });
+                });
+}
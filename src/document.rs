@@ -0,0 +1,1099 @@
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+use std::path::{Path, PathBuf};
+
+use bevy::ecs::system::{CommandQueue, SystemState};
+use bevy::prelude::*;
+use bevy_egui::EguiContexts;
+use bevy_prototype_lyon::prelude::{Fill, Stroke};
+use serde::{Deserialize, Serialize};
+
+use crate::drawing;
+use crate::flow::PlainStroke;
+use crate::history::History;
+use crate::model::{
+        AutoRoute, Description, Disruption, DisruptionSchedule, ElementId, ElementIds, ElementKind, Flow, FlowCurve,
+        FlowQuantity, Interface, ParentSystem, Perturbation, Substance, SystemNode, Throughput,
+};
+use crate::selection::{Selected, UnselectedStroke};
+
+/// Saves the diagram with Ctrl+S and opens it again with Ctrl+O.
+pub struct DocumentPlugin;
+
+impl Plugin for DocumentPlugin {
+        fn build(&self, app: &mut App) {
+                app.init_resource::<DocumentPath>()
+                        .add_systems(Update, file_shortcuts);
+        }
+}
+
+/// Version written to (and expected in) the `version` field of saved diagrams.
+/// Bump it whenever the format gains or changes fields, so older builds refuse the file
+/// instead of dropping what they don't know about and saving over it.
+///
+/// 2. Flow curves, substances and rates, interface throughput, disruption effects and
+///    schedules, and descriptions. All optional, so version 1 files read as they are.
+pub const SCHEMA_VERSION: u32 = 2;
+
+/// Where Ctrl+S saves to and Ctrl+O opens from.
+#[derive(Resource, Debug, Clone)]
+pub struct DocumentPath(pub PathBuf);
+
+impl Default for DocumentPath {
+        fn default() -> Self {
+                Self(PathBuf::from("diagram.json"))
+        }
+}
+
+#[derive(Debug)]
+pub enum DocumentError {
+        Io(std::io::Error),
+        Json(serde_json::Error),
+        UnsupportedVersion(u32),
+}
+
+impl fmt::Display for DocumentError {
+        fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+                match self {
+                        DocumentError::Io(e)   => write!(f, "{e}"),
+                        DocumentError::Json(e) => write!(f, "invalid diagram: {e}"),
+                        DocumentError::UnsupportedVersion(v) => write!(
+                                f, "diagram has schema version {v}, but this build only reads up to {SCHEMA_VERSION}"
+                        ),
+                }
+        }
+}
+
+impl std::error::Error for DocumentError {}
+
+impl From<std::io::Error> for DocumentError {
+        fn from(e: std::io::Error) -> Self {
+                DocumentError::Io(e)
+        }
+}
+
+impl From<serde_json::Error> for DocumentError {
+        fn from(e: serde_json::Error) -> Self {
+                DocumentError::Json(e)
+        }
+}
+
+/// A whole diagram as it is written to disk.
+/// Elements refer to each other by `ElementId`, never by `Entity`.
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
+pub struct Document {
+        pub version: u32,
+        #[serde(default)]
+        pub systems: Vec<SystemDoc>,
+        #[serde(default)]
+        pub interfaces: Vec<InterfaceDoc>,
+        #[serde(default)]
+        pub flows: Vec<FlowDoc>,
+        #[serde(default)]
+        pub sources: Vec<BasinDoc>,
+        #[serde(default)]
+        pub sinks: Vec<BasinDoc>,
+        #[serde(default)]
+        pub disruptions: Vec<DisruptionDoc>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct SystemDoc {
+        pub id: u64,
+        pub name: String,
+        #[serde(default)]
+        pub description: String,
+        pub centre: [f32; 2],
+        pub radius: f32,
+        #[serde(default)]
+        pub parent: Option<u64>,
+        pub style: StyleDoc,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct InterfaceDoc {
+        pub id: u64,
+        pub name: String,
+        #[serde(default)]
+        pub description: String,
+        pub system: u64,
+        /// Radians counter-clockwise from +X around the system's centre.
+        pub angle: f32,
+        /// Most let through per second; no limit if `None`.
+        #[serde(default)]
+        pub capacity: Option<f32>,
+        /// Seconds it takes to get through.
+        #[serde(default)]
+        pub delay: f32,
+        pub style: StyleDoc,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct FlowDoc {
+        pub id: u64,
+        pub name: String,
+        #[serde(default)]
+        pub description: String,
+        pub start: u64,
+        pub end: u64,
+        pub from: [f32; 2],
+        pub ctrl: [f32; 2],
+        /// The second control point of a cubic curve.
+        #[serde(default)]
+        pub ctrl2: Option<[f32; 2]>,
+        pub to: [f32; 2],
+        /// Whether the control points are placed automatically, around other systems.
+        #[serde(default)]
+        pub auto_route: bool,
+        #[serde(default)]
+        pub quantity: QuantityDoc,
+        pub style: StyleDoc,
+}
+
+/// What a flow carries, and how much of it.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct QuantityDoc {
+        pub substance: Substance,
+        pub unit: String,
+        pub rate: f32,
+}
+
+impl Default for QuantityDoc {
+        fn default() -> Self {
+                Self::capture(&FlowQuantity::default())
+        }
+}
+
+impl QuantityDoc {
+        fn capture(quantity: &FlowQuantity) -> Self {
+                Self {
+                        substance: quantity.substance,
+                        unit: quantity.unit.clone(),
+                        rate: quantity.rate,
+                }
+        }
+
+        fn quantity(&self) -> FlowQuantity {
+                FlowQuantity {
+                        substance: self.substance,
+                        unit: self.unit.clone(),
+                        rate: self.rate,
+                }
+        }
+}
+
+impl InterfaceDoc {
+        fn throughput(&self) -> Throughput {
+                Throughput { capacity: self.capacity, delay: self.delay }
+        }
+}
+
+impl FlowDoc {
+        fn curve(&self) -> FlowCurve {
+                FlowCurve {
+                        from: vec2(self.from),
+                        ctrl: vec2(self.ctrl),
+                        ctrl2: self.ctrl2.map(vec2),
+                        to: vec2(self.to),
+                }
+        }
+}
+
+/// A source or a sink.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct BasinDoc {
+        pub id: u64,
+        pub name: String,
+        #[serde(default)]
+        pub description: String,
+        pub position: [f32; 2],
+        /// Direction the opening faces, radians counter-clockwise from +X.
+        pub facing: f32,
+        pub style: StyleDoc,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct DisruptionDoc {
+        pub id: u64,
+        pub name: String,
+        #[serde(default)]
+        pub description: String,
+        pub position: [f32; 2],
+        #[serde(default)]
+        pub target: Option<u64>,
+        #[serde(default)]
+        pub perturbation: Perturbation,
+        #[serde(default)]
+        pub schedule: DisruptionSchedule,
+        pub style: StyleDoc,
+}
+
+/// Colours are sRGBA components in 0..=1; `None` means the element has no fill/stroke.
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
+pub struct StyleDoc {
+        #[serde(default)]
+        pub fill: Option<[f32; 4]>,
+        #[serde(default)]
+        pub stroke: Option<[f32; 4]>,
+        #[serde(default)]
+        pub stroke_width: Option<f32>,
+}
+
+impl StyleDoc {
+        fn capture(fill: Option<&Fill>, stroke: Option<&Stroke>) -> Self {
+                Self {
+                        fill: fill.map(|f| f.color.as_rgba_f32()),
+                        stroke: stroke.map(|s| s.color.as_rgba_f32()),
+                        stroke_width: stroke.map(|s| s.options.line_width),
+                }
+        }
+
+        fn apply(&self, commands: &mut Commands, entity: Entity) {
+                let mut entity = commands.entity(entity);
+                if let Some([r, g, b, a]) = self.fill {
+                        entity.insert(Fill::color(Color::rgba(r, g, b, a)));
+                }
+                if let Some([r, g, b, a]) = self.stroke {
+                        entity.insert(Stroke::new(Color::rgba(r, g, b, a), self.stroke_width.unwrap_or(1.)));
+                }
+        }
+}
+
+/// The spawn functions hand out a fresh identity and the default style,
+/// so swap in the saved ones.
+fn restore(
+        commands: &mut Commands,
+        entities: &mut HashMap<u64, Entity>,
+        entity: Entity,
+        id: u64,
+        name: &str,
+        description: &str,
+        style: &StyleDoc,
+) {
+        commands.entity(entity).insert(ElementId(id));
+        refresh(commands, entity, name, description, style);
+        entities.insert(id, entity);
+}
+
+/// Puts the saved name, description and style back on an element that already exists.
+fn refresh(commands: &mut Commands, entity: Entity, name: &str, description: &str, style: &StyleDoc) {
+        commands.entity(entity).insert((Name::new(name.to_string()), Description(description.to_string())));
+        // the stroke being put back is the element's own; Sankey styling takes it from there
+        commands.entity(entity).remove::<PlainStroke>();
+        style.apply(commands, entity);
+}
+
+fn vec2(v: [f32; 2]) -> Vec2 {
+        Vec2::from_array(v)
+}
+
+/// Rotation of a 2D transform around Z.
+fn rotation_z(transform: &Transform) -> f32 {
+        transform.rotation.to_euler(EulerRot::ZYX).0
+}
+
+impl Document {
+        /// Reads every element in `world` into a document.
+        pub fn capture(world: &mut World) -> Self {
+                let mut doc = Document {
+                        version: SCHEMA_VERSION,
+                        ..default()
+                };
+
+                let mut q_elements = world.query::<(
+                        Entity, &ElementId, &ElementKind, &Name, &Transform, Option<&Fill>, Option<&Stroke>, Option<&UnselectedStroke>,
+                        Option<&PlainStroke>, Option<&Description>,
+                )>();
+                let ids: HashMap<Entity, u64> = q_elements
+                        .iter(world)
+                        .map(|(entity, id, ..)| (entity, id.0))
+                        .collect();
+                let id_of = |entity: Entity| ids.get(&entity).copied();
+
+                let mut elements: Vec<_> = q_elements.iter(world).collect();
+                elements.sort_by_key(|(_, id, ..)| **id);
+
+                for (entity, id, kind, name, transform, fill, stroke, unselected, plain, description) in elements {
+                        let id = id.0;
+                        let name = name.to_string();
+                        let description = description.map(|d| d.0.clone()).unwrap_or_default();
+                        // save the element's own stroke, not its selection highlight or Sankey styling
+                        let stroke = plain.map(|p| &p.0).or(unselected.map(|u| &u.0)).or(stroke);
+                        let style = StyleDoc::capture(fill, stroke);
+                        let position = transform.translation.xy().to_array();
+                        match kind {
+                                ElementKind::System => {
+                                        let Some(system) = world.get::<SystemNode>(entity) else { continue };
+                                        doc.systems.push(SystemDoc {
+                                                id,
+                                                name,
+                                                description,
+                                                centre: position,
+                                                radius: system.radius,
+                                                parent: world.get::<ParentSystem>(entity).and_then(|p| id_of(p.0)),
+                                                style,
+                                        });
+                                }
+                                ElementKind::Interface => {
+                                        let Some(interface) = world.get::<Interface>(entity) else { continue };
+                                        let Some(system) = id_of(interface.system) else { continue };
+                                        let throughput = world.get::<Throughput>(entity).copied().unwrap_or_default();
+                                        doc.interfaces.push(InterfaceDoc {
+                                                id,
+                                                name,
+                                                description,
+                                                system,
+                                                angle: interface.angle,
+                                                capacity: throughput.capacity,
+                                                delay: throughput.delay,
+                                                style,
+                                        });
+                                }
+                                ElementKind::Flow => {
+                                        let (Some(flow), Some(curve)) = (world.get::<Flow>(entity), world.get::<FlowCurve>(entity)) else {
+                                                continue;
+                                        };
+                                        let (Some(start), Some(end)) = (id_of(flow.start), id_of(flow.end)) else { continue };
+                                        doc.flows.push(FlowDoc {
+                                                id,
+                                                name,
+                                                description,
+                                                start,
+                                                end,
+                                                from: curve.from.to_array(),
+                                                ctrl: curve.ctrl.to_array(),
+                                                ctrl2: curve.ctrl2.map(|ctrl2| ctrl2.to_array()),
+                                                to: curve.to.to_array(),
+                                                auto_route: world.get::<AutoRoute>(entity).is_some(),
+                                                quantity: world.get::<FlowQuantity>(entity).map(QuantityDoc::capture).unwrap_or_default(),
+                                                style,
+                                        });
+                                }
+                                ElementKind::Source => doc.sources.push(BasinDoc {
+                                        id,
+                                        name,
+                                        description,
+                                        position,
+                                        facing: rotation_z(transform),
+                                        style,
+                                }),
+                                ElementKind::Sink => doc.sinks.push(BasinDoc {
+                                        id,
+                                        name,
+                                        description,
+                                        position,
+                                        // sinks are drawn opening towards -X
+                                        facing: rotation_z(transform) + std::f32::consts::PI,
+                                        style,
+                                }),
+                                ElementKind::Disruption => {
+                                        let disruption = world.get::<Disruption>(entity).copied().unwrap_or_default();
+                                        doc.disruptions.push(DisruptionDoc {
+                                                id,
+                                                name,
+                                                description,
+                                                position,
+                                                target: disruption.target.and_then(id_of),
+                                                perturbation: disruption.perturbation,
+                                                schedule: disruption.schedule,
+                                                style,
+                                        });
+                                }
+                        }
+                }
+                doc
+        }
+
+        /// How many systems `system` is nested in.
+        fn system_level(&self, system: &SystemDoc) -> usize {
+                let mut level = 0;
+                let mut parent = system.parent;
+                // a hand-edited file could contain a cycle, so never walk further than there are systems
+                while let Some(id) = parent.filter(|_| level < self.systems.len()) {
+                        level += 1;
+                        parent = self.systems.iter().find(|s| s.id == id).and_then(|s| s.parent);
+                }
+                level
+        }
+
+        /// Spawns every element of the document, rebuilding its lyon shapes.
+        /// Returns the entity each saved id was spawned as.
+        pub fn spawn(&self, commands: &mut Commands, ids: &mut ElementIds) -> HashMap<u64, Entity> {
+                self.spawn_missing(commands, ids, HashMap::new())
+        }
+
+        /// Ids of every element in the document.
+        pub fn ids(&self) -> impl Iterator<Item = u64> + '_ {
+                self.systems.iter().map(|s| s.id)
+                        .chain(self.interfaces.iter().map(|i| i.id))
+                        .chain(self.flows.iter().map(|f| f.id))
+                        .chain(self.sources.iter().chain(&self.sinks).map(|b| b.id))
+                        .chain(self.disruptions.iter().map(|d| d.id))
+        }
+
+        /// Spawns the elements whose id isn't in `entities` yet, which maps ids to the entities
+        /// already standing in for them. Returns `entities` with the spawned ones added.
+        fn spawn_missing(
+                &self,
+                commands: &mut Commands,
+                ids: &mut ElementIds,
+                mut entities: HashMap<u64, Entity>,
+        ) -> HashMap<u64, Entity> {
+                let existing: HashSet<u64> = entities.keys().copied().collect();
+                let missing = |id: u64| !existing.contains(&id);
+
+                for system in self.systems.iter().filter(|s| missing(s.id)) {
+                        let level = self.system_level(system);
+                        let entity = drawing::spawn_system(commands, ids, vec2(system.centre), system.radius, level);
+                        restore(commands, &mut entities, entity, system.id, &system.name, &system.description, &system.style);
+                }
+                for system in self.systems.iter().filter(|s| missing(s.id)) {
+                        if let Some(&parent) = system.parent.and_then(|p| entities.get(&p)) {
+                                commands.entity(entities[&system.id]).insert(ParentSystem(parent));
+                        }
+                }
+                for source in self.sources.iter().filter(|s| missing(s.id)) {
+                        let entity = drawing::spawn_source(commands, ids, vec2(source.position), source.facing);
+                        restore(commands, &mut entities, entity, source.id, &source.name, &source.description, &source.style);
+                }
+                for sink in self.sinks.iter().filter(|s| missing(s.id)) {
+                        let entity = drawing::spawn_sink(commands, ids, vec2(sink.position), sink.facing);
+                        restore(commands, &mut entities, entity, sink.id, &sink.name, &sink.description, &sink.style);
+                }
+                for interface in self.interfaces.iter().filter(|i| missing(i.id)) {
+                        let (Some(parent), Some(&system)) = (
+                                self.systems.iter().find(|s| s.id == interface.system),
+                                entities.get(&interface.system),
+                        ) else {
+                                warn!("interface {} is on missing system {}", interface.id, interface.system);
+                                continue;
+                        };
+                        let entity = drawing::spawn_interface(
+                                commands,
+                                ids,
+                                system,
+                                interface.angle,
+                                (vec2(parent.centre), parent.radius),
+                        );
+                        commands.entity(entity).insert(interface.throughput());
+                        restore(commands, &mut entities, entity, interface.id, &interface.name, &interface.description, &interface.style);
+                }
+                for flow in self.flows.iter().filter(|f| missing(f.id)) {
+                        let (Some(&start), Some(&end)) = (entities.get(&flow.start), entities.get(&flow.end)) else {
+                                warn!("flow {} has a missing endpoint", flow.id);
+                                continue;
+                        };
+                        let entity = drawing::spawn_flow(commands, ids, start, end, flow.curve());
+                        commands.entity(entity).insert(flow.quantity.quantity());
+                        if flow.auto_route {
+                                commands.entity(entity).insert(AutoRoute);
+                        }
+                        restore(commands, &mut entities, entity, flow.id, &flow.name, &flow.description, &flow.style);
+                }
+                for disruption in self.disruptions.iter().filter(|d| missing(d.id)) {
+                        let target = disruption.target.and_then(|t| entities.get(&t)).copied();
+                        let entity = drawing::spawn_disruption(commands, ids, vec2(disruption.position), target);
+                        commands.entity(entity).insert(Disruption {
+                                target,
+                                perturbation: disruption.perturbation,
+                                schedule: disruption.schedule,
+                        });
+                        restore(commands, &mut entities, entity, disruption.id, &disruption.name, &disruption.description, &disruption.style);
+                }
+
+                for &id in entities.keys() {
+                        ids.reserve(ElementId(id));
+                }
+                entities
+        }
+
+        /// Updates, in place, every element whose entry differs from the one in `current`.
+        fn update_changed(&self, current: &Document, commands: &mut Commands, entities: &HashMap<u64, Entity>) {
+                let entity = |id: u64| entities.get(&id).copied();
+
+                for system in self.systems.iter().filter(|s| !current.systems.contains(s)) {
+                        let Some(e) = entity(system.id) else { continue };
+                        let depth = drawing::system_depth(self.system_level(system));
+                        commands.entity(e).insert((
+                                Transform::from_translation(vec2(system.centre).extend(depth)),
+                                SystemNode { radius: system.radius },
+                        ));
+                        match system.parent.and_then(entity) {
+                                Some(parent) => commands.entity(e).insert(ParentSystem(parent)),
+                                None         => commands.entity(e).remove::<ParentSystem>(),
+                        };
+                        refresh(commands, e, &system.name, &system.description, &system.style);
+                }
+                for interface in self.interfaces.iter().filter(|i| !current.interfaces.contains(i)) {
+                        let (Some(e), Some(system)) = (entity(interface.id), entity(interface.system)) else { continue };
+                        commands.entity(e).insert((Interface { system, angle: interface.angle }, interface.throughput()));
+                        refresh(commands, e, &interface.name, &interface.description, &interface.style);
+                }
+                for flow in self.flows.iter().filter(|f| !current.flows.contains(f)) {
+                        let (Some(e), Some(start), Some(end)) = (entity(flow.id), entity(flow.start), entity(flow.end)) else {
+                                continue;
+                        };
+                        let curve = flow.curve();
+                        commands.entity(e).insert((Flow { start, end }, curve, drawing::flow_path(&curve), flow.quantity.quantity()));
+                        if flow.auto_route {
+                                commands.entity(e).insert(AutoRoute);
+                        } else {
+                                commands.entity(e).remove::<AutoRoute>();
+                        }
+                        refresh(commands, e, &flow.name, &flow.description, &flow.style);
+                }
+                for source in self.sources.iter().filter(|s| !current.sources.contains(s)) {
+                        let Some(e) = entity(source.id) else { continue };
+                        commands.entity(e).insert(drawing::source_transform(vec2(source.position), source.facing));
+                        refresh(commands, e, &source.name, &source.description, &source.style);
+                }
+                for sink in self.sinks.iter().filter(|s| !current.sinks.contains(s)) {
+                        let Some(e) = entity(sink.id) else { continue };
+                        commands.entity(e).insert(drawing::sink_transform(vec2(sink.position), sink.facing));
+                        refresh(commands, e, &sink.name, &sink.description, &sink.style);
+                }
+                for disruption in self.disruptions.iter().filter(|d| !current.disruptions.contains(d)) {
+                        let Some(e) = entity(disruption.id) else { continue };
+                        commands.entity(e).insert((
+                                Transform::from_translation(vec2(disruption.position).extend(drawing::DISRUPTION_DEPTH)),
+                                Disruption {
+                                        target: disruption.target.and_then(entity),
+                                        perturbation: disruption.perturbation,
+                                        schedule: disruption.schedule,
+                                },
+                        ));
+                        refresh(commands, e, &disruption.name, &disruption.description, &disruption.style);
+                }
+        }
+
+        /// World space box around everything in the document, if it isn't empty.
+        pub fn bounds(&self) -> Option<Rect> {
+                let mut bounds: Option<Rect> = None;
+                let mut include = |centre: Vec2, half_size: f32| {
+                        let rect = Rect::from_center_half_size(centre, Vec2::splat(half_size));
+                        bounds = Some(bounds.map_or(rect, |b| b.union(rect)));
+                };
+
+                for system in &self.systems {
+                        include(vec2(system.centre), system.radius);
+                }
+                for interface in &self.interfaces {
+                        if let Some(system) = self.systems.iter().find(|s| s.id == interface.system) {
+                                let position = vec2(system.centre) + Vec2::from_angle(interface.angle) * system.radius;
+                                include(position, 120.);
+                        }
+                }
+                for basin in self.sources.iter().chain(&self.sinks) {
+                        include(vec2(basin.position), drawing::BASIN_WALL.max(drawing::BASIN_WIDTH / 2.));
+                }
+                for flow in &self.flows {
+                        for point in [flow.from, flow.ctrl, flow.to].into_iter().chain(flow.ctrl2) {
+                                include(vec2(point), 15.);
+                        }
+                }
+                for disruption in &self.disruptions {
+                        include(vec2(disruption.position), 40.);
+                }
+                bounds
+        }
+
+        /// The ids in `ids` plus everything that can't exist without them: the subsystems and
+        /// interfaces of removed systems, and the flows running to or from anything removed.
+        pub fn dependents(&self, ids: &HashSet<u64>) -> HashSet<u64> {
+                let mut removed = ids.clone();
+                loop {
+                        let before = removed.len();
+                        for system in &self.systems {
+                                if system.parent.is_some_and(|p| removed.contains(&p)) {
+                                        removed.insert(system.id);
+                                }
+                        }
+                        for interface in &self.interfaces {
+                                if removed.contains(&interface.system) {
+                                        removed.insert(interface.id);
+                                }
+                        }
+                        for flow in &self.flows {
+                                if removed.contains(&flow.start) || removed.contains(&flow.end) {
+                                        removed.insert(flow.id);
+                                }
+                        }
+                        if removed.len() == before {
+                                return removed;
+                        }
+                }
+        }
+
+        /// Drops the elements in `ids`; the caller is expected to have included their dependents.
+        /// Disruptions aimed at a dropped element are kept, but no longer target anything.
+        pub fn remove(&mut self, ids: &HashSet<u64>) {
+                self.systems.retain(|s| !ids.contains(&s.id));
+                self.interfaces.retain(|i| !ids.contains(&i.id));
+                self.flows.retain(|f| !ids.contains(&f.id));
+                self.sources.retain(|s| !ids.contains(&s.id));
+                self.sinks.retain(|s| !ids.contains(&s.id));
+                self.disruptions.retain(|d| !ids.contains(&d.id));
+                for disruption in &mut self.disruptions {
+                        if disruption.target.is_some_and(|t| ids.contains(&t)) {
+                                disruption.target = None;
+                        }
+                }
+        }
+
+        /// A document holding the elements in `ids` and whatever they take along: subsystems and
+        /// interfaces of the systems, and flows between copied elements. Flows, interfaces and
+        /// links to anything that isn't copied are left out.
+        pub fn extract(&self, ids: &HashSet<u64>) -> Document {
+                let mut kept = ids.clone();
+                // flows only come along when both ends do, so leave them out of the cascade
+                for flow in &self.flows {
+                        kept.remove(&flow.id);
+                }
+                let mut kept = self.dependents(&kept);
+                for flow in &self.flows {
+                        let selected_or_carried = ids.contains(&flow.id) || kept.contains(&flow.id);
+                        if !(selected_or_carried && kept.contains(&flow.start) && kept.contains(&flow.end)) {
+                                kept.remove(&flow.id);
+                        }
+                }
+
+                let keep = |id: &u64| kept.contains(id);
+                let mut doc = Document {
+                        version: SCHEMA_VERSION,
+                        systems: self.systems.iter().filter(|s| keep(&s.id)).cloned().collect(),
+                        interfaces: self.interfaces.iter().filter(|i| keep(&i.id) && keep(&i.system)).cloned().collect(),
+                        flows: self.flows.iter().filter(|f| keep(&f.id)).cloned().collect(),
+                        sources: self.sources.iter().filter(|s| keep(&s.id)).cloned().collect(),
+                        sinks: self.sinks.iter().filter(|s| keep(&s.id)).cloned().collect(),
+                        disruptions: self.disruptions.iter().filter(|d| keep(&d.id)).cloned().collect(),
+                };
+                for system in &mut doc.systems {
+                        system.parent = system.parent.filter(keep);
+                }
+                for disruption in &mut doc.disruptions {
+                        disruption.target = disruption.target.filter(keep);
+                }
+                doc
+        }
+
+        /// Gives every element a fresh id, so the document can be added to a diagram next to
+        /// the elements it was copied from.
+        pub fn with_fresh_ids(&self, ids: &mut ElementIds) -> Document {
+                let mut map = HashMap::new();
+                for id in self.ids() {
+                        map.insert(id, ids.next().0);
+                }
+                // everything in the document is in the map, anything else was left out on purpose
+                let fresh = |id: u64| map.get(&id).copied();
+
+                let mut doc = self.clone();
+                for system in &mut doc.systems {
+                        system.id = map[&system.id];
+                        system.parent = system.parent.and_then(fresh);
+                }
+                doc.interfaces.retain_mut(|interface| {
+                        interface.id = map[&interface.id];
+                        fresh(interface.system).map(|system| interface.system = system).is_some()
+                });
+                doc.flows.retain_mut(|flow| {
+                        flow.id = map[&flow.id];
+                        match (fresh(flow.start), fresh(flow.end)) {
+                                (Some(start), Some(end)) => {
+                                        (flow.start, flow.end) = (start, end);
+                                        true
+                                }
+                                _ => false,
+                        }
+                });
+                for basin in doc.sources.iter_mut().chain(&mut doc.sinks) {
+                        basin.id = map[&basin.id];
+                }
+                for disruption in &mut doc.disruptions {
+                        disruption.id = map[&disruption.id];
+                        disruption.target = disruption.target.and_then(fresh);
+                }
+                doc
+        }
+
+        /// Moves every element by `offset`.
+        pub fn translate(&mut self, offset: Vec2) {
+                let shift = |point: &mut [f32; 2]| *point = (vec2(*point) + offset).to_array();
+                for system in &mut self.systems {
+                        shift(&mut system.centre);
+                }
+                for flow in &mut self.flows {
+                        shift(&mut flow.from);
+                        shift(&mut flow.ctrl);
+                        shift(&mut flow.to);
+                        if let Some(ctrl2) = &mut flow.ctrl2 {
+                                shift(ctrl2);
+                        }
+                }
+                for basin in self.sources.iter_mut().chain(&mut self.sinks) {
+                        shift(&mut basin.position);
+                }
+                for disruption in &mut self.disruptions {
+                        shift(&mut disruption.position);
+                }
+        }
+
+        /// Adds the elements of `other`, whose ids must not clash with ours.
+        pub fn merge(&mut self, other: Document) {
+                self.systems.extend(other.systems);
+                self.interfaces.extend(other.interfaces);
+                self.flows.extend(other.flows);
+                self.sources.extend(other.sources);
+                self.sinks.extend(other.sinks);
+                self.disruptions.extend(other.disruptions);
+        }
+
+        pub fn to_json(&self) -> Result<String, DocumentError> {
+                Ok(serde_json::to_string_pretty(self)?)
+        }
+
+        pub fn from_json(json: &str) -> Result<Self, DocumentError> {
+                let mut doc: Document = serde_json::from_str(json)?;
+                if doc.version > SCHEMA_VERSION {
+                        return Err(DocumentError::UnsupportedVersion(doc.version));
+                }
+                // older versions only lack fields, which default
+                doc.version = SCHEMA_VERSION;
+                Ok(doc)
+        }
+
+        pub fn save(&self, path: &Path) -> Result<(), DocumentError> {
+                std::fs::write(path, self.to_json()?)?;
+                Ok(())
+        }
+
+        pub fn open(path: &Path) -> Result<Self, DocumentError> {
+                Self::from_json(&std::fs::read_to_string(path)?)
+        }
+}
+
+/// Brings the elements in `world` in line with `doc`, touching only what differs: elements
+/// missing from `doc` are despawned, new ones spawned and changed ones updated in place, so
+/// untouched entities (and everything pointing at them) survive. Drops the selection.
+pub fn sync_world(world: &mut World, doc: &Document) {
+        let current = Document::capture(world);
+        let mut q_elements = world.query::<(Entity, &ElementId)>();
+        let mut entities: HashMap<u64, Entity> = q_elements.iter(world).map(|(entity, id)| (id.0, entity)).collect();
+        let mut q_selected = world.query_filtered::<(Entity, Option<&UnselectedStroke>), With<Selected>>();
+        let selected: Vec<(Entity, Option<UnselectedStroke>)> = q_selected.iter(world).map(|(e, s)| (e, s.copied())).collect();
+        let wanted: HashSet<u64> = doc.ids().collect();
+
+        let mut queue = CommandQueue::default();
+        world.resource_scope(|world, mut ids: Mut<ElementIds>| {
+                let mut commands = Commands::new(&mut queue, world);
+                // put the highlighted strokes back first, so they don't overwrite restyled ones later
+                for (entity, unselected) in selected {
+                        let mut entity = commands.entity(entity);
+                        entity.remove::<(Selected, UnselectedStroke)>();
+                        if let Some(unselected) = unselected {
+                                entity.insert(unselected.0);
+                        }
+                }
+                entities.retain(|id, entity| {
+                        let keep = wanted.contains(id);
+                        if !keep {
+                                commands.entity(*entity).despawn_recursive();
+                        }
+                        keep
+                });
+                let entities = doc.spawn_missing(&mut commands, &mut ids, entities);
+                doc.update_changed(&current, &mut commands, &entities);
+        });
+        queue.apply(world);
+}
+
+/// Replaces every element in `world` with the ones in `doc`.
+pub fn replace_world(world: &mut World, doc: &Document) {
+        let mut q_elements = world.query_filtered::<Entity, With<ElementKind>>();
+        let old: Vec<Entity> = q_elements.iter(world).collect();
+
+        let mut queue = CommandQueue::default();
+        world.resource_scope(|world, mut ids: Mut<ElementIds>| {
+                let mut commands = Commands::new(&mut queue, world);
+                for entity in old {
+                        commands.entity(entity).despawn_recursive();
+                }
+                doc.spawn(&mut commands, &mut ids);
+        });
+        queue.apply(world);
+}
+
+fn file_shortcuts(world: &mut World, egui: &mut SystemState<EguiContexts>) {
+        // Ctrl+S and Ctrl+O mean something else while typing
+        if egui.get_mut(world).ctx_mut().wants_keyboard_input() {
+                return;
+        }
+        let keyboard_input = world.resource::<ButtonInput<KeyCode>>();
+        if !keyboard_input.any_pressed([KeyCode::ControlLeft, KeyCode::ControlRight]) {
+                return;
+        }
+        let save = keyboard_input.just_pressed(KeyCode::KeyS);
+        let open = keyboard_input.just_pressed(KeyCode::KeyO);
+        let path = world.resource::<DocumentPath>().0.clone();
+
+        if save {
+                match Document::capture(world).save(&path) {
+                        Ok(())   => info!("saved diagram to {}", path.display()),
+                        Err(e) => error!("could not save {}: {e}", path.display()),
+                }
+        } else if open {
+                match Document::open(&path) {
+                        Ok(doc) => {
+                                replace_world(world, &doc);
+                                // edits to the previous diagram can't be undone into this one
+                                let opened = Document::capture(world);
+                                world.resource_mut::<History>().reset(opened);
+                                info!("opened diagram {}", path.display());
+                        }
+                        Err(e) => error!("could not open {}: {e}", path.display()),
+                }
+        }
+}
+
+#[cfg(test)]
+mod tests {
+        use super::*;
+
+        fn system(id: u64, centre: [f32; 2], radius: f32, parent: Option<u64>) -> SystemDoc {
+                SystemDoc {
+                        id,
+                        name: format!("system {id}"),
+                        description: String::new(),
+                        centre,
+                        radius,
+                        parent,
+                        style: StyleDoc::default(),
+                }
+        }
+
+        fn interface(id: u64, system: u64, angle: f32) -> InterfaceDoc {
+                InterfaceDoc {
+                        id,
+                        name: format!("interface {id}"),
+                        description: String::new(),
+                        system,
+                        angle,
+                        capacity: None,
+                        delay: 0.,
+                        style: StyleDoc::default(),
+                }
+        }
+
+        fn flow(id: u64, start: u64, end: u64, from: [f32; 2], to: [f32; 2]) -> FlowDoc {
+                FlowDoc {
+                        id,
+                        name: format!("flow {id}"),
+                        description: String::new(),
+                        start,
+                        end,
+                        from,
+                        ctrl: from,
+                        ctrl2: None,
+                        to,
+                        auto_route: false,
+                        quantity: QuantityDoc::default(),
+                        style: StyleDoc::default(),
+                }
+        }
+
+        fn basin(id: u64, position: [f32; 2]) -> BasinDoc {
+                BasinDoc {
+                        id,
+                        name: format!("basin {id}"),
+                        description: String::new(),
+                        position,
+                        facing: 0.,
+                        style: StyleDoc::default(),
+                }
+        }
+
+        fn disruption(id: u64, position: [f32; 2], target: Option<u64>) -> DisruptionDoc {
+                DisruptionDoc {
+                        id,
+                        name: format!("disruption {id}"),
+                        description: String::new(),
+                        position,
+                        target,
+                        perturbation: Perturbation::default(),
+                        schedule: DisruptionSchedule::default(),
+                        style: StyleDoc::default(),
+                }
+        }
+
+        /// A source (4) feeding a system (1) through its interface (3) by flow 6, which flows on
+        /// into a sink (5) by flow 7. The system holds a subsystem (2), and disruptions are aimed
+        /// at flow 6 (8) and the sink (9).
+        fn pond() -> Document {
+                Document {
+                        version: SCHEMA_VERSION,
+                        systems: vec![system(1, [0., 0.], 100., None), system(2, [20., 0.], 30., Some(1))],
+                        interfaces: vec![interface(3, 1, 0.)],
+                        flows: vec![flow(6, 4, 3, [-300., 0.], [100., 0.]), flow(7, 3, 5, [100., 0.], [300., 0.])],
+                        sources: vec![basin(4, [-300., 0.])],
+                        sinks: vec![basin(5, [300., 0.])],
+                        disruptions: vec![disruption(8, [-100., 50.], Some(6)), disruption(9, [300., 50.], Some(5))],
+                }
+        }
+
+        fn set(ids: &[u64]) -> HashSet<u64> {
+                ids.iter().copied().collect()
+        }
+
+        fn sorted(ids: impl Iterator<Item = u64>) -> Vec<u64> {
+                let mut ids: Vec<u64> = ids.collect();
+                ids.sort_unstable();
+                ids
+        }
+
+        #[test]
+        fn json_round_trip() {
+                let mut doc = pond();
+                doc.systems[0].description = "where the rain ends up".to_string();
+                doc.systems[0].style.fill = Some([0.2, 0.4, 0.6, 1.]);
+                doc.interfaces[0].capacity = Some(2.5);
+                doc.flows[0].ctrl2 = Some([-50., 80.]);
+                doc.flows[0].auto_route = true;
+                doc.flows[0].quantity.rate = 3.;
+                doc.disruptions[0].perturbation = Perturbation::ReduceCapacity { to: 0.5 };
+                doc.disruptions[0].schedule = DisruptionSchedule::Random { rate: 0.1, duration: 4. };
+
+                let read = Document::from_json(&doc.to_json().unwrap()).unwrap();
+                assert_eq!(read, doc);
+        }
+
+        #[test]
+        fn save_and_open() {
+                let path = std::env::temp_dir().join(format!("backdrop-document-test-{}.json", std::process::id()));
+                let doc = pond();
+                doc.save(&path).unwrap();
+                let opened = Document::open(&path);
+                std::fs::remove_file(&path).unwrap();
+                assert_eq!(opened.unwrap(), doc);
+        }
+
+        #[test]
+        fn rejects_newer_versions() {
+                let json = format!(r#"{{"version": {}}}"#, SCHEMA_VERSION + 1);
+                assert!(matches!(
+                        Document::from_json(&json),
+                        Err(DocumentError::UnsupportedVersion(v)) if v == SCHEMA_VERSION + 1
+                ));
+        }
+
+        #[test]
+        fn dependents_of_a_system() {
+                let doc = pond();
+                // its subsystem, its interface, and both flows through the interface
+                assert_eq!(sorted(doc.dependents(&set(&[1])).into_iter()), [1, 2, 3, 6, 7]);
+                // only the flow into it
+                assert_eq!(sorted(doc.dependents(&set(&[5])).into_iter()), [5, 7]);
+                // a flow takes nothing along
+                assert_eq!(sorted(doc.dependents(&set(&[6])).into_iter()), [6]);
+        }
+
+        #[test]
+        fn remove_detaches_disruptions() {
+                let mut doc = pond();
+                let removed = doc.dependents(&set(&[5]));
+                doc.remove(&removed);
+                assert_eq!(sorted(doc.ids()), [1, 2, 3, 4, 6, 8, 9]);
+                assert_eq!(doc.disruptions[0].target, Some(6));
+                assert_eq!(doc.disruptions[1].target, None);
+        }
+
+        #[test]
+        fn fresh_ids_keep_links() {
+                let doc = pond();
+                let mut ids = ElementIds::default();
+                ids.reserve(ElementId(100));
+                let copy = doc.with_fresh_ids(&mut ids);
+
+                assert!(copy.ids().all(|id| id > 100));
+                assert_eq!(copy.ids().collect::<HashSet<_>>().len(), doc.ids().count());
+                assert_eq!(copy.systems[1].parent, Some(copy.systems[0].id));
+                assert_eq!(copy.interfaces[0].system, copy.systems[0].id);
+                assert_eq!((copy.flows[0].start, copy.flows[0].end), (copy.sources[0].id, copy.interfaces[0].id));
+                assert_eq!((copy.flows[1].start, copy.flows[1].end), (copy.interfaces[0].id, copy.sinks[0].id));
+                assert_eq!(copy.disruptions[0].target, Some(copy.flows[0].id));
+                assert_eq!(copy.disruptions[1].target, Some(copy.sinks[0].id));
+        }
+
+        #[test]
+        fn fresh_ids_drop_links_to_what_was_left_out() {
+                // the sink and its disruption, without the flow into it
+                let part = pond().extract(&set(&[5, 9]));
+                assert_eq!(sorted(part.ids()), [5, 9]);
+
+                let mut doc = part.clone();
+                doc.flows.push(flow(10, 4, 5, [0., 0.], [1., 1.]));
+                doc.interfaces.push(interface(11, 1, 0.));
+                doc.disruptions[0].target = Some(1);
+                let copy = doc.with_fresh_ids(&mut ElementIds::default());
+                assert_eq!(copy.sinks.len(), 1);
+                assert!(copy.flows.is_empty());
+                assert!(copy.interfaces.is_empty());
+                assert_eq!(copy.disruptions[0].target, None);
+        }
+
+        #[test]
+        fn paste_a_translated_copy() {
+                let mut doc = pond();
+                let mut ids = ElementIds::default();
+                ids.reserve(ElementId(doc.ids().max().unwrap()));
+                let mut copy = doc.extract(&set(&[1])).with_fresh_ids(&mut ids);
+                copy.translate(Vec2::new(10., -5.));
+
+                // the system comes with its subsystem and interface, but not the flows out of it
+                assert_eq!(copy.systems.len(), 2);
+                assert_eq!(copy.interfaces.len(), 1);
+                assert!(copy.flows.is_empty());
+                assert_eq!(copy.systems[0].centre, [10., -5.]);
+                assert_eq!(copy.systems[1].centre, [30., -5.]);
+
+                let before = doc.ids().count();
+                doc.merge(copy);
+                assert_eq!(doc.ids().count(), before + 3);
+                assert_eq!(doc.ids().collect::<HashSet<_>>().len(), before + 3);
+                assert_eq!(doc.systems[0].centre, [0., 0.]);
+        }
+
+        #[test]
+        fn translate_moves_every_point() {
+                let mut doc = pond();
+                doc.flows[0].ctrl2 = Some([0., 0.]);
+                doc.translate(Vec2::new(1., 2.));
+                assert_eq!(doc.systems[0].centre, [1., 2.]);
+                assert_eq!(doc.flows[0].from, [-299., 2.]);
+                assert_eq!(doc.flows[0].ctrl2, Some([1., 2.]));
+                assert_eq!(doc.flows[1].to, [301., 2.]);
+                assert_eq!(doc.sources[0].position, [-299., 2.]);
+                assert_eq!(doc.disruptions[1].position, [301., 52.]);
+                // interfaces sit at an angle on their system, which has moved
+                assert_eq!(doc.interfaces[0].angle, 0.);
+        }
+
+        #[test]
+        fn reads_version_1() {
+                let json = r#"{
+                        "version": 1,
+                        "systems": [{"id": 1, "name": "Pond", "centre": [0.0, 0.0], "radius": 100.0, "style": {}}],
+                        "interfaces": [{"id": 2, "name": "Inlet", "system": 1, "angle": 0.0, "style": {}}],
+                        "flows": [{"id": 4, "name": "Rain", "start": 3, "end": 2,
+                                   "from": [-300.0, 0.0], "ctrl": [-200.0, 0.0], "to": [100.0, 0.0], "style": {}}],
+                        "sources": [{"id": 3, "name": "Sky", "position": [-300.0, 0.0], "facing": 0.0, "style": {}}],
+                        "disruptions": [{"id": 5, "name": "Drought", "position": [0.0, 50.0], "target": 4, "style": {}}]
+                }"#;
+                let doc = Document::from_json(json).unwrap();
+                assert_eq!(doc.version, SCHEMA_VERSION);
+                assert_eq!(doc.systems[0].description, "");
+                assert_eq!(doc.interfaces[0].capacity, None);
+                assert_eq!(doc.flows[0].ctrl2, None);
+                assert_eq!(doc.flows[0].quantity, QuantityDoc::default());
+                assert_eq!(doc.disruptions[0].target, Some(4));
+                assert_eq!(doc.disruptions[0].schedule, DisruptionSchedule::default());
+        }
+}
@@ -0,0 +1,305 @@
+use std::f32::consts::PI;
+use bevy::prelude::*;
+use bevy_prototype_lyon::prelude::*;
+
+use crate::model::{Disruption, ElementBundle, ElementIds, ElementKind, Flow, FlowCurve, FlowQuantity, Interface, Sink, Source, SystemNode, Throughput};
+
+/// Background colour of the canvas.
+pub const CANVAS_COLOR: Color = Color::ANTIQUE_WHITE;
+
+// depth of each kind of element, so interfaces sit on top of systems etc.
+pub const SYSTEM_DEPTH: f32     = 0.;
+pub const FLOW_DEPTH: f32       = 1.;
+pub const SINK_DEPTH: f32       = 3.;
+pub const SOURCE_DEPTH: f32     = 3.;
+pub const INTERFACE_DEPTH: f32  = 4.;
+pub const DISRUPTION_DEPTH: f32 = 5.;
+pub const LABEL_DEPTH: f32      = 6.;
+
+/// How much higher each level of subsystems is drawn than the system it sits in.
+const SUBSYSTEM_DEPTH_STEP: f32 = 0.01;
+
+/// Depth of a system nested `level` systems deep (0 for top-level systems).
+pub fn system_depth(level: usize) -> f32 {
+        SYSTEM_DEPTH + level as f32 * SUBSYSTEM_DEPTH_STEP
+}
+
+/// Length of the side walls of sink and source basins.
+pub const BASIN_WALL: f32 = 75.;
+/// Width of the opening of sink and source basins.
+pub const BASIN_WIDTH: f32 = 150.;
+
+pub fn system_path(radius: f32) -> Path {
+        GeometryBuilder::build_as(&shapes::Circle {
+                radius,
+                center: Vec2::ZERO,
+        })
+}
+
+/// A basin open to the left, with its back wall on the local origin.
+pub fn sink_path() -> Path {
+        let (wall, basin) = (BASIN_WALL, BASIN_WIDTH);
+
+        let mut path_builder = PathBuilder::new();
+        // start point
+        path_builder.move_to(Vec2::new(-wall, -basin / 2.));
+        // wall line
+        path_builder.line_to(Vec2::new(0., -basin / 2.));
+        // basin line
+        path_builder.line_to(Vec2::new(0., basin / 2.));
+        // wall line
+        path_builder.line_to(Vec2::new(-wall, basin / 2.));
+
+        path_builder.build()
+}
+
+/// The mirror image of the sink: a basin open to the right.
+pub fn source_path() -> Path {
+        let (wall, basin) = (BASIN_WALL, BASIN_WIDTH);
+
+        let mut path_builder = PathBuilder::new();
+        path_builder.move_to(Vec2::new(wall, -basin / 2.));
+        path_builder.line_to(Vec2::new(0., -basin / 2.));
+        path_builder.line_to(Vec2::new(0., basin / 2.));
+        path_builder.line_to(Vec2::new(wall, basin / 2.));
+
+        path_builder.build()
+}
+
+/// A lightning bolt, centred on the local origin.
+pub fn disruption_path() -> Path {
+        let shape = shapes::Polygon {
+                points: vec![
+                        Vec2::new(  8.,  40.),
+                        Vec2::new(-18.,  -4.),
+                        Vec2::new( -2.,  -4.),
+                        Vec2::new(-10., -40.),
+                        Vec2::new( 18.,   8.),
+                        Vec2::new(  2.,   8.),
+                ],
+                closed: true,
+        };
+        GeometryBuilder::build_as(&shape)
+}
+
+/// A flow's curve with an arrowhead at its end, pointing the way the curve arrives there.
+pub fn flow_path(curve: &FlowCurve) -> Path {
+        let mut path_builder = PathBuilder::new();
+        let to = curve.to;
+        // line
+        path_builder.move_to(curve.from);
+        match curve.ctrl2 {
+                None => path_builder.quadratic_bezier_to(curve.ctrl, to),
+                Some(ctrl2) => path_builder.cubic_bezier_to(curve.ctrl, ctrl2, to),
+        };
+
+        // arrow
+        let heading = curve.end_heading();
+        let base_up   = heading.rotate(Vec2::new(0., 10.));
+        let base_down = heading.rotate(Vec2::new(0., -10.));
+        let tip       = heading.rotate(Vec2::new(15., 0.));
+        path_builder.line_to(to + base_up);
+        path_builder.line_to(to + tip);
+        path_builder.line_to(to + base_down);
+        path_builder.line_to(to);
+
+        path_builder.build()
+}
+
+/// Draws a dashed line with gizmos, dashes and gaps both `dash` long.
+pub fn dashed_line(gizmos: &mut Gizmos, from: Vec2, to: Vec2, dash: f32, color: Color) {
+        let length = from.distance(to);
+        if length <= 0. || dash <= 0. {
+                return;
+        }
+        let step = (to - from) / length;
+        let mut start = 0.;
+        while start < length {
+                let end = (start + dash).min(length);
+                gizmos.line_2d(from + step * start, from + step * end, color);
+                start += 2. * dash;
+        }
+}
+
+/// A bracket open towards +X, centred on the local origin.
+pub fn interface_path() -> Path {
+        let points = [
+                Vec2::new(11., 5.), // top right
+                Vec2::new(1.,  5.), // top left
+                Vec2::new(1.,  1.), // bottom left
+                Vec2::new(11., 1.), // bottom right
+        ].map(|x| x * 10.);
+
+        //             Top Right        Bottom Left
+        let centre_x = (points[0][0] + points[2][0]) / 2.;
+        let centre_y = (points[0][1] + points[2][1]) / 2.;
+        let centroid = Vec2::new(centre_x, centre_y);
+        let points = points.map(|x| x - centroid);
+
+        let shape = shapes::RoundedPolygon {
+                points: points.into_iter().collect(),
+                radius: 5.,
+                closed: false,
+        };
+        GeometryBuilder::build_as(&shape)
+}
+
+/// Spawns a system; `level` is how many systems it is nested in, so subsystems draw on top of their parent.
+pub fn spawn_system(
+        commands: &mut Commands,
+        ids: &mut ElementIds,
+        centre: Vec2,
+        radius: f32,
+        level: usize,
+) -> Entity {
+        commands.spawn((
+                ShapeBundle {
+                        path: system_path(radius),
+                        spatial: SpatialBundle {
+                                transform: Transform::from_translation(centre.extend(system_depth(level))),
+                                ..default()
+                        },
+                        ..default()
+                },
+                Fill::color(Color::ORANGE_RED),
+                Stroke::new(Color::BLACK, 5.0),
+                SystemNode { radius },
+                ElementBundle::new(ids, ElementKind::System),
+        )).id()
+}
+
+/// Where a sink at `position` whose opening faces `facing` sits.
+pub fn sink_transform(position: Vec2, facing: f32) -> Transform {
+        // the sink's opening faces -X before rotation
+        Transform::from_translation(position.extend(SINK_DEPTH))
+                .with_rotation(Quat::from_rotation_z(facing - PI))
+}
+
+/// Where a source at `position` whose opening faces `facing` sits.
+pub fn source_transform(position: Vec2, facing: f32) -> Transform {
+        Transform::from_translation(position.extend(SOURCE_DEPTH))
+                .with_rotation(Quat::from_rotation_z(facing))
+}
+
+/// Spawns a sink whose opening faces `facing` (radians counter-clockwise from +X).
+pub fn spawn_sink(
+        commands: &mut Commands,
+        ids: &mut ElementIds,
+        position: Vec2,
+        facing: f32,
+) -> Entity {
+        commands.spawn((
+                ShapeBundle {
+                        path: sink_path(),
+                        spatial: SpatialBundle {
+                                transform: sink_transform(position, facing),
+                                ..default()
+                        },
+                        ..default()
+                },
+                Stroke::new(Color::BLACK, 5.0),
+                Sink,
+                ElementBundle::new(ids, ElementKind::Sink),
+        )).id()
+}
+
+/// Spawns a source whose opening faces `facing` (radians counter-clockwise from +X).
+pub fn spawn_source(
+        commands: &mut Commands,
+        ids: &mut ElementIds,
+        position: Vec2,
+        facing: f32,
+) -> Entity {
+        commands.spawn((
+                ShapeBundle {
+                        path: source_path(),
+                        spatial: SpatialBundle {
+                                transform: source_transform(position, facing),
+                                ..default()
+                        },
+                        ..default()
+                },
+                Stroke::new(Color::BLACK, 5.0),
+                Source,
+                ElementBundle::new(ids, ElementKind::Source),
+        )).id()
+}
+
+pub fn spawn_disruption(
+        commands: &mut Commands,
+        ids: &mut ElementIds,
+        position: Vec2,
+        target: Option<Entity>,
+) -> Entity {
+        commands.spawn((
+                ShapeBundle {
+                        path: disruption_path(),
+                        spatial: SpatialBundle {
+                                transform: Transform::from_translation(position.extend(DISRUPTION_DEPTH)),
+                                ..default()
+                        },
+                        ..default()
+                },
+                Stroke::new(Color::BLACK, 2.0),
+                Fill::color(Color::GOLD),
+                Disruption { target, ..default() },
+                ElementBundle::new(ids, ElementKind::Disruption),
+        )).id()
+}
+
+/// Spawns a flow between `start` and `end`, drawn along `curve`.
+pub fn spawn_flow(
+        commands: &mut Commands,
+        ids: &mut ElementIds,
+        start: Entity,
+        end: Entity,
+        curve: FlowCurve,
+) -> Entity {
+        commands.spawn((
+                ShapeBundle {
+                        path: flow_path(&curve),
+                        spatial: SpatialBundle {
+                                transform: Transform::from_xyz(0., 0., FLOW_DEPTH),
+                                ..default()
+                        },
+                        ..default()
+                },
+                Stroke::new(Color::BLACK, 3.0),
+                Flow { start, end },
+                curve,
+                FlowQuantity::default(),
+                ElementBundle::new(ids, ElementKind::Flow),
+        )).id()
+}
+
+/// Where an interface at `angle` on a system's boundary sits: on the boundary,
+/// with its local +X along the outward normal.
+pub fn interface_transform(centre: Vec2, radius: f32, angle: f32) -> Transform {
+        let position = centre + Vec2::from_angle(angle) * radius;
+        Transform::from_translation(position.extend(INTERFACE_DEPTH))
+                .with_rotation(Quat::from_rotation_z(angle))
+}
+
+pub fn spawn_interface(
+        commands: &mut Commands,
+        ids: &mut ElementIds,
+        system: Entity,
+        angle: f32,
+        (centre, radius): (Vec2, f32),
+) -> Entity {
+        commands.spawn((
+                ShapeBundle {
+                        path: interface_path(),
+                        spatial: SpatialBundle {
+                                transform: interface_transform(centre, radius, angle),
+                                ..default()
+                        },
+                        ..default()
+                },
+                Stroke::new(Color::BLACK, 3.0),
+                Fill::color(Color::WHITE),
+                Interface { system, angle },
+                Throughput::default(),
+                ElementBundle::new(ids, ElementKind::Interface),
+        )).id()
+}
@@ -0,0 +1,181 @@
+use std::collections::HashSet;
+use bevy::prelude::*;
+use bevy_egui::{EguiClipboard, EguiContexts};
+use serde::{Deserialize, Serialize};
+
+use crate::document::{self, Document};
+use crate::helper::MyWorldCoords;
+use crate::history::Edit;
+use crate::model::{ElementId, ElementIds};
+use crate::selection::Selected;
+
+/// Deletes, duplicates, copies and pastes the selection.
+///
+/// Delete or Backspace removes the selection along with whatever depends on it, Ctrl+D
+/// duplicates it, and Ctrl+C / Ctrl+V go through the system clipboard as JSON, so elements
+/// can be copied from one window of the app into another.
+pub struct EditingPlugin;
+
+impl Plugin for EditingPlugin {
+        fn build(&self, app: &mut App) {
+                app.init_resource::<LocalClipboard>()
+                        .add_event::<EditCommand>()
+                        .add_systems(Update, (
+                                edit_shortcuts,
+                                apply_edit_commands,
+                        ).chain());
+        }
+}
+
+/// Marks clipboard text as elements copied from this app, rather than anything else that
+/// happens to be JSON.
+const CLIPBOARD_FORMAT: &str = "backdropbuilddemo/elements";
+
+/// How far a duplicate lands from the original, in world units.
+const DUPLICATE_OFFSET: Vec2 = Vec2::new(30., -30.);
+
+/// What goes on the clipboard.
+#[derive(Serialize, Deserialize)]
+struct ClipboardPayload {
+        format: String,
+        elements: Document,
+}
+
+/// The last thing copied, for when there is no system clipboard to be had.
+#[derive(Resource, Default)]
+struct LocalClipboard(Option<String>);
+
+#[derive(Event, Debug, Clone, Copy)]
+enum EditCommand {
+        Delete,
+        Duplicate,
+        Copy,
+        Paste,
+}
+
+fn edit_shortcuts(
+        mut contexts: EguiContexts,
+        keyboard_input: Res<ButtonInput<KeyCode>>,
+        mut edit_commands: EventWriter<EditCommand>,
+) {
+        // keys typed into a text field aren't meant for the canvas
+        if contexts.ctx_mut().wants_keyboard_input() {
+                return;
+        }
+        let ctrl = keyboard_input.any_pressed([KeyCode::ControlLeft, KeyCode::ControlRight]);
+
+        if keyboard_input.any_just_pressed([KeyCode::Delete, KeyCode::Backspace]) {
+                edit_commands.send(EditCommand::Delete);
+        }
+        if ctrl && keyboard_input.just_pressed(KeyCode::KeyD) {
+                edit_commands.send(EditCommand::Duplicate);
+        }
+        if ctrl && keyboard_input.just_pressed(KeyCode::KeyC) {
+                edit_commands.send(EditCommand::Copy);
+        }
+        if ctrl && keyboard_input.just_pressed(KeyCode::KeyV) {
+                edit_commands.send(EditCommand::Paste);
+        }
+}
+
+fn apply_edit_commands(world: &mut World) {
+        let edit_commands: Vec<EditCommand> = world.resource_mut::<Events<EditCommand>>().drain().collect();
+        for command in edit_commands {
+                let mut q_selected = world.query_filtered::<&ElementId, With<Selected>>();
+                let selected: HashSet<u64> = q_selected.iter(world).map(|id| id.0).collect();
+
+                match command {
+                        EditCommand::Delete if !selected.is_empty() => {
+                                let mut doc = Document::capture(world);
+                                // what goes along with the selection counts as deleted too
+                                let removed = doc.dependents(&selected);
+                                doc.remove(&removed);
+                                document::sync_world(world, &doc);
+                                world.send_event(Edit(count_label("Delete", removed.len())));
+                        }
+                        EditCommand::Duplicate if !selected.is_empty() => {
+                                let copied = Document::capture(world).extract(&selected);
+                                let added = add_elements(world, copied, DUPLICATE_OFFSET);
+                                if added > 0 {
+                                        world.send_event(Edit(count_label("Duplicate", added)));
+                                }
+                        }
+                        EditCommand::Copy if !selected.is_empty() => {
+                                let payload = ClipboardPayload {
+                                        format: CLIPBOARD_FORMAT.to_string(),
+                                        elements: Document::capture(world).extract(&selected),
+                                };
+                                match serde_json::to_string(&payload) {
+                                        Ok(json) => {
+                                                if let Some(mut clipboard) = world.get_resource_mut::<EguiClipboard>() {
+                                                        clipboard.set_contents(&json);
+                                                }
+                                                world.resource_mut::<LocalClipboard>().0 = Some(json);
+                                        }
+                                        Err(e) => error!("could not copy the selection: {e}"),
+                                }
+                        }
+                        EditCommand::Paste => {
+                                let json = world
+                                        .get_resource::<EguiClipboard>()
+                                        .and_then(|clipboard| clipboard.get_contents())
+                                        .or_else(|| world.resource::<LocalClipboard>().0.clone());
+                                let Some(payload) = json
+                                        .and_then(|json| serde_json::from_str::<ClipboardPayload>(&json).ok())
+                                        .filter(|payload| payload.format == CLIPBOARD_FORMAT)
+                                else {
+                                        continue;
+                                };
+                                // centre what's pasted on the cursor
+                                let cursor = world.resource::<MyWorldCoords>().0;
+                                let offset = payload.elements.bounds().map_or(Vec2::ZERO, |b| cursor - b.center());
+                                let added = add_elements(world, payload.elements, offset);
+                                if added > 0 {
+                                        world.send_event(Edit(count_label("Paste", added)));
+                                }
+                        }
+                        // nothing selected
+                        _ => {}
+                }
+        }
+}
+
+/// Adds copies of `elements`, moved by `offset`, to the diagram and selects them instead of
+/// whatever was selected. Returns how many elements were added.
+fn add_elements(world: &mut World, elements: Document, offset: Vec2) -> usize {
+        let mut elements = elements.with_fresh_ids(&mut world.resource_mut::<ElementIds>());
+        elements.translate(offset);
+
+        let mut doc = Document::capture(world);
+        // like placing a system, putting one down inside another makes it a subsystem
+        for system in elements.systems.iter_mut().filter(|s| s.parent.is_none()) {
+                let centre = Vec2::from_array(system.centre);
+                system.parent = doc.systems
+                        .iter()
+                        .filter(|parent| Vec2::from_array(parent.centre).distance(centre) < parent.radius)
+                        .min_by(|a, b| a.radius.total_cmp(&b.radius))
+                        .map(|parent| parent.id);
+        }
+        let added: HashSet<u64> = elements.ids().collect();
+        doc.merge(elements);
+        document::sync_world(world, &doc);
+
+        let mut q_elements = world.query::<(Entity, &ElementId)>();
+        let new: Vec<Entity> = q_elements
+                .iter(world)
+                .filter(|(_, id)| added.contains(&id.0))
+                .map(|(entity, _)| entity)
+                .collect();
+        for entity in new {
+                world.entity_mut(entity).insert(Selected);
+        }
+        added.len()
+}
+
+/// "Delete element", "Delete 3 elements" and so on.
+fn count_label(verb: &str, count: usize) -> String {
+        match count {
+                1 => format!("{verb} element"),
+                n => format!("{verb} {n} elements"),
+        }
+}
@@ -0,0 +1,339 @@
+use bevy::prelude::*;
+use bevy_egui::egui;
+use bevy_prototype_lyon::prelude::*;
+
+use crate::drawing;
+use crate::helper::MainCamera;
+use crate::history::Edit;
+use crate::interface;
+use crate::model::{AutoRoute, ElementKind, Flow, FlowCurve, FlowQuantity, Substance, SystemNode};
+use crate::selection::{self, Selected, UnselectedStroke};
+
+/// Keeps every flow attached to its two ends. Whenever either end moves the curve follows
+/// it, keeping its bend, and the path and arrowhead are rebuilt.
+///
+/// A selected flow shows handles on its control points, which the select tool can drag to
+/// reshape it. The inspector edits what it carries and how much, switches it between a
+/// quadratic and a cubic curve, or has it routed around other systems automatically.
+///
+/// With Sankey styling on, every flow is drawn as wide as its rate calls for, relative to
+/// the largest one, and coloured by its substance.
+pub struct FlowPlugin;
+
+impl Plugin for FlowPlugin {
+        fn build(&self, app: &mut App) {
+                app.init_resource::<FlowStyling>()
+                        .add_systems(Update, (
+                                (connect_flows.after(interface::update_interface), redraw_flows).chain(),
+                                style_flows,
+                                draw_ctrl_handles,
+                        ));
+        }
+}
+
+/// How flows are drawn; toggled from the toolbar.
+#[derive(Resource, Debug, Default)]
+pub struct FlowStyling {
+        /// Derive each flow's stroke width and colour from what it carries.
+        pub sankey: bool,
+}
+
+/// A flow's own stroke, kept aside while Sankey styling draws it differently.
+/// Anything that persists strokes should prefer this over `UnselectedStroke` and `Stroke`.
+#[derive(Component, Debug, Clone, Copy)]
+pub struct PlainStroke(pub Stroke);
+
+// Sankey stroke widths, in world units
+const SANKEY_MIN_WIDTH: f32 = 1.;
+const SANKEY_MAX_WIDTH: f32 = 24.;
+
+/// How big control point handles are on screen, in pixels; also how close a click has to be to grab one.
+pub const HANDLE_RADIUS: f32 = 6.;
+const HANDLE_COLOR: Color = Color::rgb(0.1, 0.45, 0.95);
+
+/// How far auto-routed flows keep from the systems they go around.
+const ROUTE_CLEARANCE: f32 = 15.;
+/// Auto-routing tries bends this far apart, as a fraction of the flow's length...
+const ROUTE_STEP: f32 = 0.1;
+/// ...on either side, up to this many times, before giving up and going straight.
+const ROUTE_TRIES: usize = 20;
+/// How many points along a candidate curve are checked against the systems.
+const ROUTE_SAMPLES: usize = 32;
+
+/// Anything a flow can start or end at.
+type Endpoints<'w, 's> = Query<'w, 's, (&'static ElementKind, &'static Transform, Option<&'static SystemNode>), Without<Flow>>;
+
+/// Where a flow attaches to an element, given the point the flow comes from (or goes to).
+/// Systems are joined on their boundary, facing that point; sources and sinks at their opening.
+pub fn anchor(kind: ElementKind, transform: &Transform, system: Option<&SystemNode>, toward: Vec2) -> Vec2 {
+        let position = transform.translation.xy();
+        match (kind, system) {
+                (ElementKind::System, Some(system)) => position + (toward - position).normalize_or_zero() * system.radius,
+                (ElementKind::Source, _) => transform.transform_point(Vec3::X * drawing::BASIN_WALL).xy(),
+                (ElementKind::Sink, _)   => transform.transform_point(Vec3::NEG_X * drawing::BASIN_WALL).xy(),
+                _ => position,
+        }
+}
+
+/// Moves one of a curve's control points along with new ends, so it keeps the same place
+/// relative to the line between them: it turns and stretches with that line.
+fn carry_ctrl(curve: &FlowCurve, ctrl: Vec2, from: Vec2, to: Vec2) -> Vec2 {
+        let (old_axis, new_axis) = (curve.to - curve.from, to - from);
+        let length_squared = old_axis.length_squared();
+        if length_squared < f32::EPSILON || new_axis.length_squared() < f32::EPSILON {
+                // no line to measure against, so just shift it with the midpoint
+                return ctrl + (from + to - curve.from - curve.to) / 2.;
+        }
+        let offset = ctrl - curve.from;
+        let along  = offset.dot(old_axis) / length_squared;
+        let across = offset.dot(old_axis.perp()) / length_squared;
+        from + new_axis * along + new_axis.perp() * across
+}
+
+/// A control point for a flow from `from` to `to` that bends it around every system in
+/// `obstacles` (as centre and radius), as gently as will do. If no bend clears them all the
+/// flow goes straight.
+pub fn route_around(from: Vec2, to: Vec2, obstacles: &[(Vec2, f32)]) -> Vec2 {
+        let mid = from.lerp(to, 0.5);
+        let normal = (to - from).perp().normalize_or_zero();
+        let step = from.distance(to) * ROUTE_STEP;
+        let clears = |ctrl: Vec2| {
+                let curve = FlowCurve::quadratic(from, ctrl, to);
+                (0..=ROUTE_SAMPLES).all(|i| {
+                        let point = curve.point_at(i as f32 / ROUTE_SAMPLES as f32);
+                        obstacles.iter().all(|&(centre, radius)| point.distance(centre) > radius + ROUTE_CLEARANCE)
+                })
+        };
+        (0..=ROUTE_TRIES)
+                .flat_map(|i| [1., -1.].map(|side| mid + normal * side * step * i as f32))
+                .find(|&ctrl| clears(ctrl))
+                .unwrap_or(mid)
+}
+
+fn connect_flows(
+        mut q_flows: Query<(&Flow, &mut FlowCurve, Has<AutoRoute>)>,
+        q_endpoints: Endpoints,
+        q_systems: Query<(&SystemNode, &Transform)>,
+) {
+        let systems: Vec<(Vec2, f32)> = q_systems.iter().map(|(system, transform)| (transform.translation.xy(), system.radius)).collect();
+
+        for (flow, mut curve, auto_route) in &mut q_flows {
+                let (Ok(start), Ok(end)) = (q_endpoints.get(flow.start), q_endpoints.get(flow.end)) else {
+                        continue;
+                };
+                let (start_centre, end_centre) = (start.1.translation.xy(), end.1.translation.xy());
+                let from = anchor(*start.0, start.1, start.2, end_centre);
+                let to = anchor(*end.0, end.1, end.2, start_centre);
+
+                if auto_route {
+                        // the systems the flow starts or ends in (or on) aren't in its way
+                        let obstacles: Vec<(Vec2, f32)> = systems
+                                .iter()
+                                .copied()
+                                .filter(|&(centre, radius)| {
+                                        centre.distance(from) > radius + ROUTE_CLEARANCE && centre.distance(to) > radius + ROUTE_CLEARANCE
+                                })
+                                .collect();
+                        let routed = FlowCurve::quadratic(from, route_around(from, to, &obstacles), to);
+                        curve.set_if_neq(routed);
+                } else if from != curve.from || to != curve.to {
+                        *curve = FlowCurve {
+                                from,
+                                ctrl: carry_ctrl(&curve, curve.ctrl, from, to),
+                                ctrl2: curve.ctrl2.map(|ctrl2| carry_ctrl(&curve, ctrl2, from, to)),
+                                to,
+                        };
+                }
+        }
+}
+
+/// Rebuilds a flow's path and arrowhead whenever its curve changes.
+fn redraw_flows(
+        mut q_flows: Query<(&FlowCurve, &mut Path), Changed<FlowCurve>>,
+) {
+        for (curve, mut path) in &mut q_flows {
+                *path = drawing::flow_path(curve);
+        }
+}
+
+/// Draws the control points of selected flows, each tied to the end of the curve it pulls on.
+fn draw_ctrl_handles(
+        mut gizmos: Gizmos,
+        q_camera: Query<&OrthographicProjection, With<MainCamera>>,
+        q_flows: Query<(&FlowCurve, &InheritedVisibility), With<Selected>>,
+) {
+        let scale = q_camera.get_single().map_or(1., |projection| projection.scale);
+        for (curve, visibility) in &q_flows {
+                if !visibility.get() {
+                        continue;
+                }
+                match curve.ctrl2 {
+                        None => {
+                                gizmos.line_2d(curve.from, curve.ctrl, HANDLE_COLOR);
+                                gizmos.line_2d(curve.ctrl, curve.to, HANDLE_COLOR);
+                        }
+                        Some(ctrl2) => {
+                                gizmos.line_2d(curve.from, curve.ctrl, HANDLE_COLOR);
+                                gizmos.line_2d(ctrl2, curve.to, HANDLE_COLOR);
+                        }
+                }
+                for ctrl in curve.controls() {
+                        gizmos.circle_2d(ctrl, HANDLE_RADIUS * scale, HANDLE_COLOR);
+                }
+        }
+}
+
+/// The selected flows, with what the inspector edits on them.
+pub type SelectedFlows<'w, 's> = Query<'w, 's, (
+        Entity,
+        &'static mut FlowCurve,
+        &'static mut FlowQuantity,
+        Has<AutoRoute>,
+), With<Selected>>;
+
+/// Lets a flow be given a substance, rate and unit, switched between a quadratic and a cubic
+/// curve, or routed automatically.
+pub fn flow_properties(
+        ui: &mut egui::Ui,
+        commands: &mut Commands,
+        edits: &mut EventWriter<Edit>,
+        entity: Entity,
+        mut curve: Mut<FlowCurve>,
+        mut quantity: Mut<FlowQuantity>,
+        auto_route: bool,
+) {
+        let mut edited = quantity.clone();
+        let mut finished = false;
+        egui::Grid::new("flow quantity").num_columns(2).show(ui, |ui| {
+                ui.label("Substance");
+                egui::ComboBox::from_id_source("flow substance")
+                        .selected_text(edited.substance.label())
+                        .show_ui(ui, |ui| {
+                                for substance in Substance::ALL {
+                                        ui.selectable_value(&mut edited.substance, substance, substance.label());
+                                }
+                        });
+                ui.end_row();
+
+                ui.label("Rate");
+                let rate = ui.add(egui::DragValue::new(&mut edited.rate)
+                        .clamp_range(0.0..=f32::MAX)
+                        .speed(0.1)
+                        .suffix(format!(" {}", quantity.unit)));
+                ui.end_row();
+
+                ui.label("Unit");
+                let unit = ui.add(egui::TextEdit::singleline(&mut edited.unit).desired_width(80.));
+                ui.end_row();
+
+                // dragging the rate or typing the unit is one edit, once it's done
+                finished = rate.drag_released() || rate.lost_focus() || unit.lost_focus();
+        });
+        if edited.substance != quantity.substance {
+                // keep the unit in step, unless it was given by hand
+                if edited.unit == quantity.substance.default_unit() {
+                        edited.unit = edited.substance.default_unit().to_string();
+                }
+                edits.send(Edit("Change flow substance".to_string()));
+        }
+        if finished {
+                edits.send(Edit("Change flow quantity".to_string()));
+        }
+        quantity.set_if_neq(edited);
+        ui.separator();
+
+        // auto-routing picks its own (quadratic) curve
+        ui.add_enabled_ui(!auto_route, |ui| {
+                ui.horizontal(|ui| {
+                        if ui.radio(!curve.is_cubic(), "Quadratic").clicked() && curve.is_cubic() {
+                                *curve = curve.to_quadratic();
+                                edits.send(Edit("Make flow quadratic".to_string()));
+                        }
+                        if ui.radio(curve.is_cubic(), "Cubic").clicked() && !curve.is_cubic() {
+                                *curve = curve.to_cubic();
+                                edits.send(Edit("Make flow cubic".to_string()));
+                        }
+                });
+        });
+        let mut route = auto_route;
+        if ui.checkbox(&mut route, "Route around systems").changed() {
+                if route {
+                        commands.entity(entity).insert(AutoRoute);
+                        edits.send(Edit("Auto-route flow".to_string()));
+                } else {
+                        commands.entity(entity).remove::<AutoRoute>();
+                        edits.send(Edit("Stop auto-routing flow".to_string()));
+                }
+        }
+}
+
+type StyledFlows<'w, 's> = Query<'w, 's, (
+        Entity,
+        &'static FlowQuantity,
+        &'static mut Stroke,
+        Option<&'static mut UnselectedStroke>,
+        Option<&'static PlainStroke>,
+)>;
+
+/// Applies or lifts Sankey styling, keeping each flow's own stroke aside in the meantime.
+fn style_flows(
+        mut commands: Commands,
+        styling: Res<FlowStyling>,
+        mut q_flows: StyledFlows,
+) {
+        if styling.sankey {
+                let largest = q_flows.iter().map(|(_, quantity, ..)| quantity.rate.abs()).fold(0., f32::max);
+                for (entity, quantity, mut stroke, unselected, plain) in &mut q_flows {
+                        if plain.is_none() {
+                                let own = unselected.as_ref().map_or(*stroke, |unselected| unselected.0);
+                                commands.entity(entity).insert(PlainStroke(own));
+                        }
+                        show_stroke(&mut stroke, unselected, sankey_stroke(quantity, largest));
+                }
+        } else {
+                for (entity, _, mut stroke, unselected, plain) in &mut q_flows {
+                        let Some(plain) = plain else { continue };
+                        show_stroke(&mut stroke, unselected, plain.0);
+                        commands.entity(entity).remove::<PlainStroke>();
+                }
+        }
+}
+
+/// Draws a flow with `own` as its stroke, still highlighted if it's selected.
+fn show_stroke(stroke: &mut Mut<Stroke>, unselected: Option<Mut<UnselectedStroke>>, own: Stroke) {
+        match unselected {
+                Some(mut unselected) => {
+                        if unselected.0 != own {
+                                unselected.0 = own;
+                                **stroke = selection::highlighted(own);
+                        }
+                }
+                None => {
+                        if **stroke != own {
+                                **stroke = own;
+                        }
+                }
+        }
+}
+
+/// A flow's Sankey stroke: as wide as its rate is next to the `largest`, and coloured by its
+/// substance, washed out the less it carries.
+fn sankey_stroke(quantity: &FlowQuantity, largest: f32) -> Stroke {
+        let share = if largest > 0. { quantity.rate.abs() / largest } else { 0. };
+        let [r, g, b, _] = substance_color(quantity.substance).as_rgba_f32();
+        let strength = 0.3 + 0.7 * share;
+        let wash = |channel: f32| 1. - (1. - channel) * strength;
+        Stroke::new(
+                Color::rgb(wash(r), wash(g), wash(b)),
+                SANKEY_MIN_WIDTH + (SANKEY_MAX_WIDTH - SANKEY_MIN_WIDTH) * share,
+        )
+}
+
+/// The colour a substance is drawn in.
+pub fn substance_color(substance: Substance) -> Color {
+        match substance {
+                Substance::Matter      => Color::rgb(0.45, 0.3, 0.15),
+                Substance::Energy      => Color::rgb(0.9, 0.45, 0.),
+                Substance::Information => Color::rgb(0.15, 0.35, 0.8),
+        }
+}
@@ -0,0 +1,211 @@
+use bevy::prelude::*;
+use bevy::render::view::RenderLayers;
+use bevy::window::PrimaryWindow;
+use bevy_egui::{egui, EguiContexts};
+
+use crate::helper::{self, MainCamera, MyWorldCoords};
+
+/// A background grid whose spacing follows the zoom, rulers along the top and left of the
+/// window, and snapping to the grid when placing and moving elements. Each can be switched
+/// on and off from the toolbar.
+pub struct GridPlugin;
+
+impl Plugin for GridPlugin {
+        fn build(&self, app: &mut App) {
+                app.init_resource::<Grid>()
+                        .init_gizmo_group::<GridGizmos>()
+                        .add_systems(Startup, setup_grid)
+                        .add_systems(Update, (adapt_spacing, (draw_grid, draw_rulers)).chain());
+        }
+}
+
+/// The grid lines are never closer together than this, in screen pixels...
+const MIN_SPACING: f32 = 16.;
+/// ...and every this many of them is drawn a little darker.
+const MAJOR_EVERY: i32 = 5;
+
+/// Thickness of the rulers, in points.
+const RULER_SIZE: f32 = 20.;
+
+/// Which of the grid, rulers and snapping are on, and how far apart the grid lines are.
+#[derive(Resource, Debug)]
+pub struct Grid {
+        pub visible: bool,
+        pub rulers: bool,
+        pub snap: bool,
+        /// Follows the zoom of the main camera.
+        spacing: f32,
+}
+
+impl Default for Grid {
+        fn default() -> Self {
+                Self { visible: false, rulers: false, snap: false, spacing: spacing(1.) }
+        }
+}
+
+impl Grid {
+        /// Rounds `point` to the nearest grid intersection, if snapping is on.
+        pub fn snap(&self, point: Vec2) -> Vec2 {
+                if !self.snap {
+                        return point;
+                }
+                (point / self.spacing).round() * self.spacing
+        }
+}
+
+/// Spacing of the grid in world units at the given camera scale: the smallest 1, 2 or 5 times
+/// a power of ten that keeps the lines at least `MIN_SPACING` pixels apart.
+fn spacing(scale: f32) -> f32 {
+        let min = MIN_SPACING * scale;
+        let decade = 10_f32.powf(min.log10().floor());
+        [1., 2., 5., 10.]
+                .into_iter()
+                .map(|step| step * decade)
+                .find(|spacing| *spacing >= min)
+                .unwrap_or(10. * decade)
+}
+
+#[derive(Default, Reflect, GizmoConfigGroup)]
+struct GridGizmos;
+
+fn setup_grid(mut config_store: ResMut<GizmoConfigStore>) {
+        let (config, _) = config_store.config_mut::<GridGizmos>();
+        config.line_width = 1.;
+        // only the main camera shows it, not the minimap
+        config.render_layers = RenderLayers::layer(helper::MAIN_VIEW_LAYER);
+}
+
+/// `value` written with as many decimals as labels `step` apart need.
+fn ruler_label(value: f32, step: f32) -> String {
+        let decimals = (-step.log10()).ceil().max(0.) as usize;
+        format!("{value:.decimals$}")
+}
+
+/// What part of the world the main camera shows, and how many world units make a pixel.
+fn visible_area(window: &Window, transform: &Transform, projection: &OrthographicProjection) -> (Rect, f32) {
+        let half = Vec2::new(window.width(), window.height()) * projection.scale / 2.;
+        let centre = transform.translation.xy();
+        (Rect::from_corners(centre - half, centre + half), projection.scale)
+}
+
+/// The multiples of `spacing` between `from` and `to`, as indices.
+fn lines_between(from: f32, to: f32, spacing: f32) -> impl Iterator<Item = i32> {
+        (from / spacing).ceil() as i32..=(to / spacing).floor() as i32
+}
+
+fn adapt_spacing(
+        mut grid: ResMut<Grid>,
+        q_camera: Query<&OrthographicProjection, With<MainCamera>>,
+) {
+        let Ok(projection) = q_camera.get_single() else {
+                return;
+        };
+        let spacing = spacing(projection.scale);
+        if grid.spacing != spacing {
+                grid.spacing = spacing;
+        }
+}
+
+fn draw_grid(
+        grid: Res<Grid>,
+        mut gizmos: Gizmos<GridGizmos>,
+        q_window: Query<&Window, With<PrimaryWindow>>,
+        q_camera: Query<(&Transform, &OrthographicProjection), With<MainCamera>>,
+) {
+        if !grid.visible {
+                return;
+        }
+        let (Ok(window), Ok((transform, projection))) = (q_window.get_single(), q_camera.get_single()) else {
+                return;
+        };
+        let (area, _) = visible_area(window, transform, projection);
+        let spacing = grid.spacing;
+        let color = |index: i32| {
+                if index % MAJOR_EVERY == 0 {
+                        Color::rgba(0., 0., 0., 0.12)
+                } else {
+                        Color::rgba(0., 0., 0., 0.05)
+                }
+        };
+
+        for index in lines_between(area.min.x, area.max.x, spacing) {
+                let x = index as f32 * spacing;
+                gizmos.line_2d(Vec2::new(x, area.min.y), Vec2::new(x, area.max.y), color(index));
+        }
+        for index in lines_between(area.min.y, area.max.y, spacing) {
+                let y = index as f32 * spacing;
+                gizmos.line_2d(Vec2::new(area.min.x, y), Vec2::new(area.max.x, y), color(index));
+        }
+}
+
+/// Rulers along the top and left edges, labelled in world units, with the cursor marked.
+fn draw_rulers(
+        mut contexts: EguiContexts,
+        grid: Res<Grid>,
+        cursor: Res<MyWorldCoords>,
+        q_window: Query<&Window, With<PrimaryWindow>>,
+        q_camera: Query<(&Transform, &OrthographicProjection), With<MainCamera>>,
+) {
+        if !grid.rulers {
+                return;
+        }
+        let (Ok(window), Ok((transform, projection))) = (q_window.get_single(), q_camera.get_single()) else {
+                return;
+        };
+        let (area, scale) = visible_area(window, transform, projection);
+        let spacing = grid.spacing;
+
+        // world to screen; y runs down on screen
+        let screen_x = |x: f32| (x - area.min.x) / scale;
+        let screen_y = |y: f32| (area.max.y - y) / scale;
+
+        let ctx = contexts.ctx_mut();
+        let painter = ctx.layer_painter(egui::LayerId::new(egui::Order::Background, egui::Id::new("rulers")));
+        let screen = ctx.screen_rect();
+        let background = egui::Color32::from_rgba_unmultiplied(255, 255, 255, 230);
+        let ink = egui::Color32::from_gray(90);
+        let font = egui::FontId::monospace(9.);
+        let top = egui::Rect::from_min_max(screen.min, egui::pos2(screen.max.x, RULER_SIZE));
+        let left = egui::Rect::from_min_max(screen.min, egui::pos2(RULER_SIZE, screen.max.y));
+        painter.rect_filled(top, 0., background);
+        painter.rect_filled(left, 0., background);
+
+        for index in lines_between(area.min.x, area.max.x, spacing) {
+                let x = screen_x(index as f32 * spacing);
+                if x < RULER_SIZE {
+                        continue;
+                }
+                let major = index % MAJOR_EVERY == 0;
+                let length = if major { RULER_SIZE } else { RULER_SIZE / 4. };
+                painter.vline(x, (RULER_SIZE - length)..=RULER_SIZE, egui::Stroke::new(1., ink));
+                if major {
+                        let label = ruler_label(index as f32 * spacing, spacing * MAJOR_EVERY as f32);
+                        painter.text(egui::pos2(x + 2., 1.), egui::Align2::LEFT_TOP, label, font.clone(), ink);
+                }
+        }
+        for index in lines_between(area.min.y, area.max.y, spacing) {
+                let y = screen_y(index as f32 * spacing);
+                if y < RULER_SIZE {
+                        continue;
+                }
+                let major = index % MAJOR_EVERY == 0;
+                let length = if major { RULER_SIZE } else { RULER_SIZE / 4. };
+                painter.hline((RULER_SIZE - length)..=RULER_SIZE, y, egui::Stroke::new(1., ink));
+                if major {
+                        let label = ruler_label(index as f32 * spacing, spacing * MAJOR_EVERY as f32);
+                        painter.text(egui::pos2(1., y + 2.), egui::Align2::LEFT_TOP, label, font.clone(), ink);
+                }
+        }
+
+        // where the cursor is
+        let marker = egui::Stroke::new(1., egui::Color32::from_rgb(30, 110, 230));
+        let (x, y) = (screen_x(cursor.0.x), screen_y(cursor.0.y));
+        if x > RULER_SIZE {
+                painter.vline(x, 0.0..=RULER_SIZE, marker);
+        }
+        if y > RULER_SIZE {
+                painter.hline(0.0..=RULER_SIZE, y, marker);
+        }
+        painter.line_segment([top.left_bottom(), top.right_bottom()], egui::Stroke::new(1., ink));
+        painter.line_segment([left.right_top(), left.right_bottom()], egui::Stroke::new(1., ink));
+}
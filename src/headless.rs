@@ -0,0 +1,430 @@
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use bevy::app::{AppExit, ScheduleRunnerPlugin};
+use bevy::prelude::*;
+use bevy::render::camera::RenderTarget;
+use bevy::render::extract_resource::{ExtractResource, ExtractResourcePlugin};
+use bevy::render::render_asset::{RenderAssetUsages, RenderAssets};
+use bevy::render::render_resource::{
+        BufferDescriptor, BufferUsages, CommandEncoderDescriptor, Extent3d, ImageCopyBuffer, ImageDataLayout,
+        Maintain, MapMode, TextureDimension, TextureFormat, TextureUsages,
+};
+use bevy::render::renderer::{render_system, RenderDevice, RenderQueue};
+use bevy::render::{Render, RenderApp, RenderSet};
+use bevy::window::ExitCondition;
+use bevy::winit::WinitPlugin;
+use bevy_prototype_lyon::prelude::*;
+
+use crate::document::Document;
+use crate::drawing;
+use crate::helper;
+use crate::model::{ElementIds, ModelPlugin};
+
+pub const USAGE: &str = "usage: backdropbuilddemo render <diagram.json> -o <out.png> [--width <pixels>]";
+
+/// Widest (and tallest) image rendered, in pixels. What most GPUs can take as a texture;
+/// the device's own limit is checked as well once it's known.
+pub const MAX_SIZE: u32 = 8192;
+
+/// Frames rendered before the image is read back, so shape meshes are built
+/// and the render pipelines have finished compiling.
+const PRE_ROLL_FRAMES: u32 = 40;
+
+/// Room left around the diagram, in world units.
+const MARGIN: f32 = 40.;
+
+/// How long to wait for the image before giving up.
+const TIMEOUT: Duration = Duration::from_secs(60);
+
+/// Options of the `render` subcommand.
+#[derive(Debug, Clone)]
+pub struct RenderOptions {
+        pub input: PathBuf,
+        pub output: PathBuf,
+        pub width: u32,
+}
+
+impl RenderOptions {
+        /// Parses the arguments following `render`.
+        pub fn parse(args: &[String]) -> Result<Self, String> {
+                let mut input = None;
+                let mut output = None;
+                let mut width = 1024;
+
+                let mut args = args.iter();
+                while let Some(arg) = args.next() {
+                        match arg.as_str() {
+                                "-o" | "--output" => {
+                                        output = Some(PathBuf::from(args.next().ok_or("missing value for --output")?));
+                                }
+                                "-w" | "--width" => {
+                                        let value = args.next().ok_or("missing value for --width")?;
+                                        width = value.parse().map_err(|_| format!("invalid width: {value}"))?;
+                                        if width == 0 {
+                                                return Err("width must be at least 1".to_string());
+                                        }
+                                        if width > MAX_SIZE {
+                                                return Err(format!("width must be at most {MAX_SIZE}"));
+                                        }
+                                }
+                                _ if input.is_none() && !arg.starts_with('-') => input = Some(PathBuf::from(arg)),
+                                _ => return Err(format!("unexpected argument: {arg}")),
+                        }
+                }
+
+                Ok(Self {
+                        input: input.ok_or("missing diagram to render")?,
+                        output: output.ok_or("missing --output")?,
+                        width,
+                })
+        }
+}
+
+/// Renders the diagram in `options.input` to a PNG without opening a window.
+pub fn run(options: RenderOptions) -> Result<(), String> {
+        let document = Document::open(&options.input)
+                .map_err(|e| format!("could not open {}: {e}", options.input.display()))?;
+        let input = options.input.clone();
+        let outcome = Outcome::default();
+
+        App::new()
+                .insert_resource(Msaa::Sample4)
+                .insert_resource(ClearColor(drawing::CANVAS_COLOR))
+                .insert_resource(HeadlessJob { document, options })
+                .insert_resource(outcome.clone())
+                .add_plugins(
+                        DefaultPlugins
+                                .set(WindowPlugin {
+                                        primary_window: None,
+                                        exit_condition: ExitCondition::DontExit,
+                                        close_when_requested: false,
+                                })
+                                .disable::<WinitPlugin>(),
+                )
+                .add_plugins(ScheduleRunnerPlugin::run_loop(Duration::from_secs_f64(1. / 60.)))
+                .add_plugins(ShapePlugin)
+                .add_plugins(ModelPlugin)
+                .add_plugins(HeadlessCapturePlugin)
+                .add_systems(Startup, (
+                        helper::setup_cameras,
+                        spawn_document,
+                ).chain())
+                .add_systems(Update, save_capture)
+                .run();
+
+        let result = outcome.0.lock().unwrap().take();
+        match result {
+                Some(Ok(output)) => {
+                        info!("rendered {} to {}", input.display(), output.display());
+                        Ok(())
+                }
+                Some(Err(e)) => Err(e),
+                None => Err("the render stopped before the image was captured".to_string()),
+        }
+}
+
+#[derive(Resource)]
+struct HeadlessJob {
+        document: Document,
+        options: RenderOptions,
+}
+
+/// The offscreen image the camera renders into, shared with the render world.
+#[derive(Resource, Clone, ExtractResource)]
+struct CaptureTarget {
+        image: Handle<Image>,
+        /// Set once the pre-roll is over and the next frame should be read back.
+        ready: bool,
+}
+
+/// Pixels (or the reason there are none) read back by the render world.
+type Pixels = Result<Vec<u8>, String>;
+
+/// Hands the read back pixels over from the render world to the main world.
+#[derive(Resource, Clone, Default)]
+struct Readback(Arc<Mutex<Option<Pixels>>>);
+
+/// Where the PNG was written (or why it wasn't), for `run` to report once the app has quit.
+#[derive(Resource, Clone, Default)]
+struct Outcome(Arc<Mutex<Option<Result<PathBuf, String>>>>);
+
+struct HeadlessCapturePlugin;
+
+impl Plugin for HeadlessCapturePlugin {
+        fn build(&self, app: &mut App) {
+                let readback = Readback::default();
+                app.insert_resource(readback.clone())
+                        .add_plugins(ExtractResourcePlugin::<CaptureTarget>::default());
+                app.sub_app_mut(RenderApp)
+                        .insert_resource(readback)
+                        .add_systems(Render, copy_to_readback.after(render_system).in_set(RenderSet::Render));
+        }
+}
+
+/// Spawns the diagram and points the main camera at an image fitted around it, or gives up
+/// if that image would be too big.
+#[allow(clippy::too_many_arguments)]
+fn spawn_document(
+        mut commands: Commands,
+        mut ids: ResMut<ElementIds>,
+        mut images: ResMut<Assets<Image>>,
+        job: Res<HeadlessJob>,
+        render_device: Res<RenderDevice>,
+        outcome: Res<Outcome>,
+        mut exit: EventWriter<AppExit>,
+        mut q_camera: Query<(&mut Camera, &mut Transform, &mut OrthographicProjection), With<helper::MainCamera>>,
+) {
+        let bounds = document_bounds(&job.document);
+        let max = MAX_SIZE.min(render_device.limits().max_texture_dimension_2d);
+        let size = match image_size(bounds, job.options.width, max) {
+                Ok(size) => size,
+                Err(e) => {
+                        *outcome.0.lock().unwrap() = Some(Err(e));
+                        exit.send(AppExit);
+                        return;
+                }
+        };
+        let scale = bounds.width() / size.width as f32;
+        job.document.spawn(&mut commands, &mut ids);
+
+        let mut image = Image::new_fill(
+                size,
+                TextureDimension::D2,
+                &[0, 0, 0, 0],
+                TextureFormat::Rgba8UnormSrgb,
+                RenderAssetUsages::default(),
+        );
+        image.texture_descriptor.usage =
+                TextureUsages::TEXTURE_BINDING | TextureUsages::COPY_SRC | TextureUsages::RENDER_ATTACHMENT;
+        let image = images.add(image);
+
+        let (mut camera, mut transform, mut projection) = q_camera.single_mut();
+        camera.target = RenderTarget::Image(image.clone());
+        transform.translation = bounds.center().extend(transform.translation.z);
+        projection.scale = scale;
+
+        commands.insert_resource(CaptureTarget { image, ready: false });
+}
+
+/// Size of an image `width` pixels wide showing `bounds`, if neither side is over `max`.
+fn image_size(bounds: Rect, width: u32, max: u32) -> Result<Extent3d, String> {
+        let height = (bounds.height() * width as f32 / bounds.width()).ceil().max(1.) as u32;
+        if width > max || height > max {
+                return Err(format!(
+                        "a {width}x{height} image is too big to render, at most {max} pixels a side fit; try a smaller --width"
+                ));
+        }
+        Ok(Extent3d {
+                width,
+                height,
+                depth_or_array_layers: 1,
+        })
+}
+
+/// World-space rectangle around everything in `doc`.
+fn document_bounds(doc: &Document) -> Rect {
+        doc.bounds().unwrap_or(Rect::from_center_half_size(Vec2::ZERO, Vec2::splat(100.))).inset(MARGIN)
+}
+
+/// Runs in the render world: copies the target image into a mappable buffer
+/// and waits for the GPU to hand it back.
+fn copy_to_readback(
+        target: Option<Res<CaptureTarget>>,
+        readback: Res<Readback>,
+        gpu_images: Res<RenderAssets<Image>>,
+        render_device: Res<RenderDevice>,
+        render_queue: Res<RenderQueue>,
+) {
+        let Some(target) = target.filter(|t| t.ready) else {
+                return;
+        };
+        if readback.0.lock().unwrap().is_some() {
+                return;
+        }
+        let Some(gpu_image) = gpu_images.get(&target.image) else {
+                return;
+        };
+
+        let (width, height) = (gpu_image.size.x as u32, gpu_image.size.y as u32);
+        let row_bytes = width as usize * 4;
+        // wgpu wants each copied row padded to 256 bytes
+        let padded_row_bytes = RenderDevice::align_copy_bytes_per_row(row_bytes);
+
+        let buffer = render_device.create_buffer(&BufferDescriptor {
+                label: Some("headless readback"),
+                size: (padded_row_bytes * height as usize) as u64,
+                usage: BufferUsages::MAP_READ | BufferUsages::COPY_DST,
+                mapped_at_creation: false,
+        });
+        let mut encoder = render_device.create_command_encoder(&CommandEncoderDescriptor::default());
+        encoder.copy_texture_to_buffer(
+                gpu_image.texture.as_image_copy(),
+                ImageCopyBuffer {
+                        buffer: &buffer,
+                        layout: ImageDataLayout {
+                                offset: 0,
+                                bytes_per_row: Some(padded_row_bytes as u32),
+                                rows_per_image: None,
+                        },
+                },
+                Extent3d {
+                        width,
+                        height,
+                        depth_or_array_layers: 1,
+                },
+        );
+        render_queue.submit([encoder.finish()]);
+
+        let slice = buffer.slice(..);
+        let (sender, receiver) = std::sync::mpsc::channel();
+        render_device.map_buffer(&slice, MapMode::Read, move |result| {
+                let _ = sender.send(result);
+        });
+        render_device.poll(Maintain::Wait);
+
+        let pixels = match receiver.recv() {
+                Ok(Ok(())) => {
+                        let pixels = slice
+                                .get_mapped_range()
+                                .chunks(padded_row_bytes)
+                                .flat_map(|row| &row[..row_bytes])
+                                .copied()
+                                .collect();
+                        buffer.unmap();
+                        Ok(pixels)
+                }
+                Ok(Err(e)) => Err(e.to_string()),
+                Err(e)     => Err(e.to_string()),
+        };
+        *readback.0.lock().unwrap() = Some(pixels);
+}
+
+/// Flags the capture once the pre-roll is over, then writes the PNG and quits,
+/// leaving the outcome for `run`. Gives up if the image doesn't arrive in time.
+#[allow(clippy::too_many_arguments)]
+fn save_capture(
+        mut frames: Local<u32>,
+        time: Res<Time<Real>>,
+        job: Res<HeadlessJob>,
+        target: Option<ResMut<CaptureTarget>>,
+        readback: Res<Readback>,
+        outcome: Res<Outcome>,
+        images: Res<Assets<Image>>,
+        mut exit: EventWriter<AppExit>,
+) {
+        let mut finish = |result: Result<PathBuf, String>| {
+                *outcome.0.lock().unwrap() = Some(result);
+                exit.send(AppExit);
+        };
+        if time.elapsed() > TIMEOUT {
+                finish(Err(format!("timed out after {}s waiting for the rendered image", TIMEOUT.as_secs())));
+                return;
+        }
+
+        let Some(mut target) = target else {
+                return;
+        };
+        *frames += 1;
+        if *frames == PRE_ROLL_FRAMES {
+                target.ready = true;
+        }
+
+        let Some(pixels) = readback.0.lock().unwrap().take() else {
+                return;
+        };
+        finish(write_png(pixels, &target, &images, &job.options.output));
+}
+
+/// Writes the read back `pixels` of the capture target to `output`.
+fn write_png(pixels: Pixels, target: &CaptureTarget, images: &Assets<Image>, output: &Path) -> Result<PathBuf, String> {
+        let pixels = pixels.map_err(|e| format!("could not read back the rendered image: {e}"))?;
+        let size = images
+                .get(&target.image)
+                .map(|image| image.texture_descriptor.size)
+                .ok_or("the rendered image is gone")?;
+
+        let image = Image::new(
+                size,
+                TextureDimension::D2,
+                pixels,
+                TextureFormat::Rgba8UnormSrgb,
+                RenderAssetUsages::default(),
+        );
+        let image = image
+                .try_into_dynamic()
+                .map_err(|e| format!("could not convert the rendered image: {e:?}"))?;
+        image
+                .to_rgba8()
+                .save(output)
+                .map_err(|e| format!("could not write {}: {e}", output.display()))?;
+        Ok(output.to_path_buf())
+}
+
+#[cfg(test)]
+mod tests {
+        use super::*;
+
+        fn parse(args: &[&str]) -> Result<RenderOptions, String> {
+                RenderOptions::parse(&args.iter().map(|arg| arg.to_string()).collect::<Vec<_>>())
+        }
+
+        #[test]
+        fn defaults() {
+                let options = parse(&["d.json", "-o", "out.png"]).unwrap();
+                assert_eq!(options.input, PathBuf::from("d.json"));
+                assert_eq!(options.output, PathBuf::from("out.png"));
+                assert_eq!(options.width, 1024);
+        }
+
+        #[test]
+        fn long_flags_in_any_order() {
+                let options = parse(&["--width", "300", "--output", "out.png", "d.json"]).unwrap();
+                assert_eq!(options.input, PathBuf::from("d.json"));
+                assert_eq!(options.output, PathBuf::from("out.png"));
+                assert_eq!(options.width, 300);
+        }
+
+        #[test]
+        fn missing_output() {
+                assert_eq!(parse(&["d.json"]).unwrap_err(), "missing --output");
+                assert_eq!(parse(&["d.json", "-o"]).unwrap_err(), "missing value for --output");
+        }
+
+        #[test]
+        fn missing_input() {
+                assert_eq!(parse(&["-o", "out.png"]).unwrap_err(), "missing diagram to render");
+        }
+
+        #[test]
+        fn invalid_width() {
+                assert_eq!(parse(&["d.json", "-o", "out.png", "--width", "wide"]).unwrap_err(), "invalid width: wide");
+                assert_eq!(parse(&["d.json", "-o", "out.png", "-w", "-3"]).unwrap_err(), "invalid width: -3");
+                assert_eq!(parse(&["d.json", "-o", "out.png", "-w", "0"]).unwrap_err(), "width must be at least 1");
+                assert_eq!(parse(&["d.json", "-o", "out.png", "-w"]).unwrap_err(), "missing value for --width");
+        }
+
+        #[test]
+        fn width_cap() {
+                assert_eq!(parse(&["d.json", "-o", "out.png", "-w", &MAX_SIZE.to_string()]).unwrap().width, MAX_SIZE);
+                assert_eq!(
+                        parse(&["d.json", "-o", "out.png", "-w", &(MAX_SIZE + 1).to_string()]).unwrap_err(),
+                        format!("width must be at most {MAX_SIZE}")
+                );
+        }
+
+        #[test]
+        fn tall_images_are_refused() {
+                let tall = Rect::new(0., 0., 100., 1000.);
+                assert_eq!(image_size(tall, 100, 1000).unwrap(), Extent3d { width: 100, height: 1000, depth_or_array_layers: 1 });
+                assert!(image_size(tall, 101, 1000).is_err());
+                assert!(image_size(Rect::new(0., 0., 100., 10.), 1001, 1000).is_err());
+        }
+
+        #[test]
+        fn unexpected_arguments() {
+                assert_eq!(parse(&["d.json", "-o", "out.png", "--height", "3"]).unwrap_err(), "unexpected argument: --height");
+                assert_eq!(parse(&["d.json", "e.json", "-o", "out.png"]).unwrap_err(), "unexpected argument: e.json");
+        }
+}
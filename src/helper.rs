@@ -1,6 +1,8 @@
 use bevy::window::PrimaryWindow;
 use bevy::render::deterministic::DeterministicRenderingConfig;
+use bevy::render::view::RenderLayers;
 use bevy::prelude::*;
+use bevy_egui::EguiContexts;
 
 pub struct HelperPlugin;
 impl Plugin for HelperPlugin {
@@ -11,7 +13,8 @@ impl Plugin for HelperPlugin {
                         .add_systems(Startup, 
                                 (
                                         setup_cameras,
-                                        setup_cursor_world_position
+                                        setup_cursor_world_position,
+                                        setup_gizmos,
                                 ).chain()
                         )
                         .add_systems(OnEnter(CursorHelperState::Enabled), (
@@ -21,12 +24,11 @@ impl Plugin for HelperPlugin {
                                 despawn_cursor_position_text,
                         ))
                         .add_systems(Update, (
-                                zoom_control_system,
                                 toggle_cursor_helper,
                                 (
                                         update_cursor_position,
-                                        update_cursor_position_text
-                                ).chain().run_if(in_state(CursorHelperState::Enabled)),
+                                        update_cursor_position_text.run_if(in_state(CursorHelperState::Enabled)),
+                                ).chain(),
                         ));
         }
 }
@@ -39,11 +41,18 @@ enum CursorHelperState {
 }
 
 fn toggle_cursor_helper(
+        mut contexts: EguiContexts,
         state: Res<State<CursorHelperState>>,
         mut next_state: ResMut<NextState<CursorHelperState>>,
         keyboard_input: Res<ButtonInput<KeyCode>>,
 ) {
-        if keyboard_input.just_pressed(KeyCode::KeyC) {
+        // typing a C into a name isn't asking for this either
+        if contexts.ctx_mut().wants_keyboard_input() {
+                return;
+        }
+        // Ctrl+C is copy, not this
+        let ctrl = keyboard_input.any_pressed([KeyCode::ControlLeft, KeyCode::ControlRight]);
+        if keyboard_input.just_pressed(KeyCode::KeyC) && !ctrl {
                 match state.get() {
                         CursorHelperState::Enabled  => next_state.set(CursorHelperState::Disabled),
                         CursorHelperState::Disabled => next_state.set(CursorHelperState::Enabled),
@@ -53,19 +62,29 @@ fn toggle_cursor_helper(
 
 /// We will store the world position of the mouse cursor here.
 #[derive(Resource, Default)]
-pub struct MyWorldCoords(Vec2);
+pub struct MyWorldCoords(pub Vec2);
 
 /// Used to help identify our main camera
 #[derive(Component)]
 pub struct MainCamera;
 
 
-fn setup_cameras(
+/// Render layer of what only the main view shows, like the grid, the selection box and
+/// other gizmos, and the stock labels; the minimap leaves it out.
+pub const MAIN_VIEW_LAYER: u8 = 1;
+
+pub fn setup_cameras(
         mut commands: Commands,
         mut deterministic_rendering_config: ResMut<DeterministicRenderingConfig>,
 ) {
         deterministic_rendering_config.stable_sort_z_fighting = true;
-        commands.spawn((Camera2dBundle::default(),MainCamera));
+        commands.spawn((Camera2dBundle::default(),MainCamera, RenderLayers::from_layers(&[0, MAIN_VIEW_LAYER])));
+}
+
+/// Gizmos are drawn over the canvas as guides and handles, so only in the main view.
+fn setup_gizmos(mut config_store: ResMut<GizmoConfigStore>) {
+        let (config, _) = config_store.config_mut::<DefaultGizmoConfigGroup>();
+        config.render_layers = RenderLayers::layer(MAIN_VIEW_LAYER);
 }
 
 #[derive(Component)]
@@ -95,27 +114,24 @@ fn setup_cursor_world_position(
         }
 }
 
-fn update_cursor_position(
+/// Keeps `MyWorldCoords` current. Runs every frame rather than on `CursorMoved`, since
+/// zooming moves the world under a cursor that stays still.
+pub fn update_cursor_position(
         mut mycoords: ResMut<MyWorldCoords>,
-        mut cursor_evr: EventReader<CursorMoved>,
+        q_window: Query<&Window, With<PrimaryWindow>>,
         q_camera: Query<(&Camera, &GlobalTransform), With<MainCamera>>,
 ) {
-        // iter CursorMove events
-        for ev in cursor_evr.read() {
-                // check if cursor is outside thew window
-                if let Some(delta) = ev.delta {
-                        // check if cursor has moved
-                        if delta.x != 0.0 && delta.y != 0.0 {
-                                let (camera, camera_transform) = q_camera.single();
-
-                                // update the cursor world coordinates resource
-                                if let Some(world_position) = camera.viewport_to_world(camera_transform, ev.position)
-                                        .map(|ray| ray.origin.truncate())
-                                {
-                                        mycoords.0 = world_position;
-                                }
-                        }
-                }
+        let (camera, camera_transform) = q_camera.single();
+        let Ok(window) = q_window.get_single() else {
+                return;
+        };
+
+        // update the cursor world coordinates resource
+        if let Some(world_position) = window.cursor_position()
+                .and_then(|cursor| camera.viewport_to_world(camera_transform, cursor))
+                .map(|ray| ray.origin.truncate())
+        {
+                mycoords.0 = world_position;
         }
 }
 
@@ -161,7 +177,7 @@ fn update_cursor_position_text(
                         if delta.x != 0.0 && delta.y != 0.0 {
                                 // update the compontent text w/ new cursor position
                                 for mut text in &mut query {
-                                        let world_position = mycoords.0.clone();
+                                        let world_position = mycoords.0;
                                         text.sections[0].value = format!("({:.2}, {:.2})", world_position.x, world_position.y);
                                 }  
                         }
@@ -178,20 +194,4 @@ fn despawn_cursor_position_text(
         }        
 }
 
-fn zoom_control_system(
-        input: Res<ButtonInput<KeyCode>>,
-        mut camera_query: Query<&mut OrthographicProjection, With<MainCamera>>,
-) {
-        let mut projection = camera_query.single_mut();
-    
-        if input.pressed(KeyCode::Minus) {
-            projection.scale += 0.2;
-        }
-    
-        if input.pressed(KeyCode::Equal) {
-            projection.scale -= 0.2;
-        }
-    
-        projection.scale = projection.scale.clamp(0.2, 5.);
-}
     
\ No newline at end of file
@@ -0,0 +1,193 @@
+use std::collections::VecDeque;
+use bevy::prelude::*;
+use bevy::transform::TransformSystem;
+use bevy_egui::EguiContexts;
+
+use crate::document::{self, Document};
+use crate::selection;
+use crate::toolbar_menu;
+
+/// Undo and redo for everything done to the diagram.
+///
+/// Whatever edits the diagram (creating, deleting, moving, resizing, restyling or reconnecting
+/// elements) sends an `Edit` once it's done. The history then snapshots the diagram and keeps
+/// the states before and after it, so undoing or redoing an edit is a matter of syncing the
+/// world back to one of them. Ctrl+Z undoes, Ctrl+Shift+Z redoes.
+pub struct HistoryPlugin;
+
+impl Plugin for HistoryPlugin {
+        fn build(&self, app: &mut App) {
+                app.init_resource::<History>()
+                        .add_event::<Edit>()
+                        .add_event::<HistoryAction>()
+                        .add_systems(PostStartup, start_history)
+                        .add_systems(Update, (history_shortcuts, history_panel))
+                        .add_systems(PostUpdate, (
+                                step_history,
+                                record_edits,
+                        ).chain()
+                                .before(selection::highlight_selection)
+                                .before(TransformSystem::TransformPropagate));
+        }
+}
+
+/// How many edits can be undone.
+const MAX_HISTORY: usize = 100;
+
+/// Sent by anything that edits the diagram, once the edit is complete, to make it undoable.
+/// Holds what the history panel calls the edit, e.g. "Move 3 elements".
+#[derive(Event, Debug, Clone)]
+pub struct Edit(pub String);
+
+/// Asks the history to undo or redo this many edits.
+#[derive(Event, Debug, Clone, Copy)]
+pub enum HistoryAction {
+        Undo(usize),
+        Redo(usize),
+}
+
+struct HistoryEntry {
+        label: String,
+        before: Document,
+        after: Document,
+}
+
+#[derive(Resource, Default)]
+pub struct History {
+        undo: VecDeque<HistoryEntry>,
+        redo: Vec<HistoryEntry>,
+        /// The diagram as of the last edit (or undo/redo).
+        current: Document,
+}
+
+impl History {
+        /// Forgets every edit, and starts over from `doc`; e.g. after opening another diagram.
+        pub fn reset(&mut self, doc: Document) {
+                self.undo.clear();
+                self.redo.clear();
+                self.current = doc;
+        }
+}
+
+fn start_history(world: &mut World) {
+        let doc = Document::capture(world);
+        world.resource_mut::<History>().reset(doc);
+}
+
+/// Snapshots the diagram after every batch of edits.
+fn record_edits(world: &mut World) {
+        let Some(label) = world.resource_mut::<Events<Edit>>().drain().map(|edit| edit.0).last() else {
+                return;
+        };
+        let after = Document::capture(world);
+        let mut history = world.resource_mut::<History>();
+        // e.g. a click that didn't end up moving anything
+        if after == history.current {
+                return;
+        }
+
+        let before = std::mem::replace(&mut history.current, after.clone());
+        history.undo.push_back(HistoryEntry { label, before, after });
+        history.redo.clear();
+        if history.undo.len() > MAX_HISTORY {
+                history.undo.pop_front();
+        }
+}
+
+/// Undoes or redoes the edits asked for, then syncs the world to where that leaves the diagram.
+fn step_history(world: &mut World) {
+        let actions: Vec<HistoryAction> = world.resource_mut::<Events<HistoryAction>>().drain().collect();
+        if actions.is_empty() {
+                return;
+        }
+
+        let mut history = world.resource_mut::<History>();
+        let start = history.current.clone();
+        for action in actions {
+                match action {
+                        HistoryAction::Undo(steps) => {
+                                for _ in 0..steps {
+                                        let Some(entry) = history.undo.pop_back() else { break };
+                                        history.current = entry.before.clone();
+                                        history.redo.push(entry);
+                                }
+                        }
+                        HistoryAction::Redo(steps) => {
+                                for _ in 0..steps {
+                                        let Some(entry) = history.redo.pop() else { break };
+                                        history.current = entry.after.clone();
+                                        history.undo.push_back(entry);
+                                }
+                        }
+                }
+        }
+
+        if history.current != start {
+                let target = history.current.clone();
+                document::sync_world(world, &target);
+        }
+}
+
+fn history_shortcuts(
+        mut contexts: EguiContexts,
+        keyboard_input: Res<ButtonInput<KeyCode>>,
+        mut actions: EventWriter<HistoryAction>,
+) {
+        // leave Ctrl+Z to text fields that are being typed in
+        if contexts.ctx_mut().wants_keyboard_input() {
+                return;
+        }
+        let ctrl = keyboard_input.any_pressed([KeyCode::ControlLeft, KeyCode::ControlRight]);
+        let shift = keyboard_input.any_pressed([KeyCode::ShiftLeft, KeyCode::ShiftRight]);
+        if ctrl && keyboard_input.just_pressed(KeyCode::KeyZ) {
+                actions.send(if shift { HistoryAction::Redo(1) } else { HistoryAction::Undo(1) });
+        }
+}
+
+/// Lists the edits next to the toolbar; clicking one goes back (or forward) to right after it.
+fn history_panel(
+        mut contexts: EguiContexts,
+        history: Res<History>,
+        mut actions: EventWriter<HistoryAction>,
+) {
+        egui::Window::new("History")
+                .title_bar(false)
+                .movable(false)
+                .resizable(false)
+                .anchor(egui::Align2::RIGHT_BOTTOM, egui::Vec2::ZERO)
+                .frame(toolbar_menu::floating_frame())
+                .show(contexts.ctx_mut(), |ui| {
+                        ui.set_width(180.);
+                        ui.horizontal(|ui| {
+                                if ui.add_enabled(!history.undo.is_empty(), egui::Button::new("Undo")).clicked() {
+                                        actions.send(HistoryAction::Undo(1));
+                                }
+                                if ui.add_enabled(!history.redo.is_empty(), egui::Button::new("Redo")).clicked() {
+                                        actions.send(HistoryAction::Redo(1));
+                                }
+                        });
+                        ui.separator();
+
+                        egui::ScrollArea::vertical()
+                                .max_height(160.)
+                                .stick_to_bottom(true)
+                                .show(ui, |ui| {
+                                        let done = history.undo.len();
+                                        if ui.selectable_label(done == 0, "Start").clicked() {
+                                                actions.send(HistoryAction::Undo(done));
+                                        }
+                                        for (i, entry) in history.undo.iter().enumerate() {
+                                                if ui.selectable_label(i + 1 == done, &entry.label).clicked() {
+                                                        actions.send(HistoryAction::Undo(done - i - 1));
+                                                }
+                                        }
+                                        // undone edits are greyed out until they're redone
+                                        for (i, entry) in history.redo.iter().rev().enumerate() {
+                                                let label = egui::RichText::new(&entry.label).weak();
+                                                if ui.selectable_label(false, label).clicked() {
+                                                        actions.send(HistoryAction::Redo(i + 1));
+                                                }
+                                        }
+                                });
+                });
+}
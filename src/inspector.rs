@@ -0,0 +1,238 @@
+use bevy::prelude::*;
+use bevy_egui::{egui, EguiContexts, EguiSet};
+use bevy_prototype_lyon::prelude::*;
+
+use crate::disruption;
+use crate::flow::{self, PlainStroke, SelectedFlows};
+use crate::history::Edit;
+use crate::interface;
+use crate::model::{Description, Disruption, ElementKind, Interface, ParentSystem, SystemNode, Throughput};
+use crate::selection::{self, Selected, UnselectedStroke};
+use crate::system_node::MIN_SYSTEM_RADIUS;
+use crate::toolbar_menu;
+
+/// A side panel showing the properties of the selected element, when it's the only thing
+/// selected: its name, description and colours, and whatever else its kind has, like a
+/// system's radius, an interface's angle or what a flow carries. Editing them updates the
+/// element on the canvas as you go, and each finished change can be undone.
+pub struct InspectorPlugin;
+
+impl Plugin for InspectorPlugin {
+        fn build(&self, app: &mut App) {
+                // before the floating panels, so they're laid out beside it rather than over it
+                app.add_systems(PreUpdate, inspector_panel.after(EguiSet::BeginFrame));
+        }
+}
+
+const PANEL_WIDTH: f32 = 260.;
+const MAX_STROKE_WIDTH: f32 = 24.;
+
+/// The selected elements' colours and strokes, including the ones kept aside while they're
+/// highlighted or drawn in Sankey style.
+type SelectedStyles<'w, 's> = Query<'w, 's, (
+        Option<&'static mut Fill>,
+        Option<&'static mut Stroke>,
+        Option<&'static mut UnselectedStroke>,
+        Option<&'static mut PlainStroke>,
+), With<Selected>>;
+
+/// Every system, with what's needed to keep a subsystem inside its parent.
+type Systems<'w, 's> = Query<'w, 's, (&'static mut SystemNode, &'static Transform, Option<&'static ParentSystem>)>;
+
+fn to_egui(color: Color) -> egui::Color32 {
+        let [r, g, b, a] = color.as_rgba_u8();
+        egui::Color32::from_rgba_unmultiplied(r, g, b, a)
+}
+
+fn from_egui(color: egui::Color32) -> Color {
+        let [r, g, b, a] = color.to_srgba_unmultiplied();
+        Color::rgba_u8(r, g, b, a)
+}
+
+#[allow(clippy::too_many_arguments)]
+fn inspector_panel(
+        mut commands: Commands,
+        mut contexts: EguiContexts,
+        mut edits: EventWriter<Edit>,
+        mut recolored: Local<bool>,
+        mut q_selected: Query<(Entity, &ElementKind, &mut Name, &mut Description), With<Selected>>,
+        mut q_styles: SelectedStyles,
+        mut q_systems: Systems,
+        mut q_interfaces: Query<(&mut Interface, &mut Throughput), With<Selected>>,
+        mut q_flows: SelectedFlows,
+        mut q_disruptions: Query<&mut Disruption, With<Selected>>,
+        q_names: Query<&Name, Without<Selected>>,
+) {
+        if q_selected.iter().count() != 1 {
+                return;
+        }
+        let Ok((entity, kind, mut name, mut description)) = q_selected.get_single_mut() else {
+                return;
+        };
+        let kind = *kind;
+        // the room a subsystem has inside its parent, which bounds its radius
+        let room = q_systems
+                .get(entity)
+                .ok()
+                .and_then(|(_, transform, parent)| {
+                        let (parent_node, parent_transform, _) = q_systems.get(parent?.0).ok()?;
+                        Some(parent_node.radius - transform.translation.xy().distance(parent_transform.translation.xy()))
+                });
+
+        let ctx = contexts.ctx_mut();
+        egui::SidePanel::right("inspector")
+                .resizable(false)
+                .exact_width(PANEL_WIDTH)
+                .frame(toolbar_menu::floating_frame())
+                .show(ctx, |ui| {
+                        ui.strong(kind.label());
+                        egui::Grid::new("inspector element").num_columns(2).show(ui, |ui| {
+                                ui.label("Name");
+                                let mut edited = name.as_str().to_string();
+                                let response = ui.add(egui::TextEdit::singleline(&mut edited).desired_width(f32::INFINITY));
+                                if edited != name.as_str() {
+                                        name.set(edited);
+                                }
+                                if response.lost_focus() {
+                                        edits.send(Edit("Rename element".to_string()));
+                                }
+                                ui.end_row();
+
+                                ui.label("Description");
+                                let mut edited = description.0.clone();
+                                let response = ui.add(egui::TextEdit::multiline(&mut edited).desired_rows(3).desired_width(f32::INFINITY));
+                                if edited != description.0 {
+                                        description.0 = edited;
+                                }
+                                if response.lost_focus() {
+                                        edits.send(Edit("Describe element".to_string()));
+                                }
+                                ui.end_row();
+                        });
+
+                        if let Ok((fill, stroke, unselected, plain)) = q_styles.get_single_mut() {
+                                ui.separator();
+                                style_properties(ui, &mut edits, &mut recolored, fill, stroke, unselected, plain);
+                        }
+
+                        match kind {
+                                ElementKind::System => {
+                                        let Ok((mut system, ..)) = q_systems.get_mut(entity) else { return };
+                                        ui.separator();
+                                        egui::Grid::new("inspector system").num_columns(2).show(ui, |ui| {
+                                                ui.label("Radius");
+                                                let mut radius = system.radius;
+                                                let max = room.map_or(f32::MAX, |room| room.max(MIN_SYSTEM_RADIUS));
+                                                let response = ui.add(egui::DragValue::new(&mut radius)
+                                                        .clamp_range(MIN_SYSTEM_RADIUS..=max)
+                                                        .speed(1.));
+                                                if radius != system.radius {
+                                                        system.radius = radius;
+                                                }
+                                                if response.drag_released() || response.lost_focus() {
+                                                        edits.send(Edit("Resize system".to_string()));
+                                                }
+                                                ui.end_row();
+                                        });
+                                }
+                                ElementKind::Interface => {
+                                        let Ok((mut interface, throughput)) = q_interfaces.get_single_mut() else { return };
+                                        ui.separator();
+                                        egui::Grid::new("inspector interface").num_columns(2).show(ui, |ui| {
+                                                ui.label("Angle");
+                                                let mut degrees = interface.angle.to_degrees().rem_euclid(360.);
+                                                let response = ui.add(egui::DragValue::new(&mut degrees)
+                                                        .clamp_range(0.0..=360.0)
+                                                        .speed(1.)
+                                                        .suffix("°"));
+                                                if degrees != interface.angle.to_degrees().rem_euclid(360.) {
+                                                        interface.angle = degrees.to_radians();
+                                                }
+                                                if response.drag_released() || response.lost_focus() {
+                                                        edits.send(Edit("Move interface".to_string()));
+                                                }
+                                                ui.end_row();
+                                        });
+                                        ui.separator();
+                                        interface::throughput_properties(ui, &mut edits, throughput);
+                                }
+                                ElementKind::Flow => {
+                                        let Ok((entity, curve, quantity, auto_route)) = q_flows.get_single_mut() else { return };
+                                        ui.separator();
+                                        flow::flow_properties(ui, &mut commands, &mut edits, entity, curve, quantity, auto_route);
+                                }
+                                ElementKind::Disruption => {
+                                        let Ok(disruption) = q_disruptions.get_single_mut() else { return };
+                                        ui.separator();
+                                        let target_name = |target: Entity| q_names.get(target).map_or("?".to_string(), |name| name.to_string());
+                                        disruption::disruption_properties(ui, &mut edits, disruption, target_name);
+                                }
+                                ElementKind::Source | ElementKind::Sink => {}
+                        }
+                });
+}
+
+/// Edits an element's fill and stroke. While it's selected (or drawn in Sankey style) the
+/// stroke on show isn't its own, so the one kept aside is edited and the highlight redone.
+fn style_properties(
+        ui: &mut egui::Ui,
+        edits: &mut EventWriter<Edit>,
+        recolored: &mut bool,
+        fill: Option<Mut<Fill>>,
+        stroke: Option<Mut<Stroke>>,
+        unselected: Option<Mut<UnselectedStroke>>,
+        plain: Option<Mut<PlainStroke>>,
+) {
+        egui::Grid::new("inspector style").num_columns(2).show(ui, |ui| {
+                if let Some(mut fill) = fill {
+                        ui.label("Fill");
+                        let mut color = to_egui(fill.color);
+                        if ui.color_edit_button_srgba(&mut color).changed() {
+                                fill.color = from_egui(color);
+                                *recolored = true;
+                        }
+                        ui.end_row();
+                }
+
+                let Some(mut stroke) = stroke else { return };
+                let own = plain.as_ref().map(|plain| plain.0)
+                        .or(unselected.as_ref().map(|unselected| unselected.0))
+                        .unwrap_or(*stroke);
+                let mut edited = own;
+
+                ui.label("Stroke");
+                let mut color = to_egui(edited.color);
+                if ui.color_edit_button_srgba(&mut color).changed() {
+                        edited.color = from_egui(color);
+                        *recolored = true;
+                }
+                ui.end_row();
+
+                ui.label("Stroke width");
+                let response = ui.add(egui::DragValue::new(&mut edited.options.line_width)
+                        .clamp_range(0.0..=MAX_STROKE_WIDTH)
+                        .speed(0.1));
+                if response.drag_released() || response.lost_focus() {
+                        edits.send(Edit("Change stroke width".to_string()));
+                }
+                ui.end_row();
+
+                if edited != own {
+                        match (plain, unselected) {
+                                // Sankey styling decides what's on show until it's turned off
+                                (Some(mut plain), _) => plain.0 = edited,
+                                (None, Some(mut unselected)) => {
+                                        unselected.0 = edited;
+                                        *stroke = selection::highlighted(edited);
+                                }
+                                (None, None) => *stroke = edited,
+                        }
+                }
+        });
+
+        // the colour pickers change the colour as they're dragged around; it's one edit once closed
+        if *recolored && !ui.memory(|memory| memory.any_popup_open()) {
+                edits.send(Edit("Recolour element".to_string()));
+                *recolored = false;
+        }
+}
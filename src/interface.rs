@@ -0,0 +1,211 @@
+use std::f32::consts::{PI, TAU};
+use bevy::prelude::*;
+use bevy_egui::{egui, EguiContexts};
+use bevy_prototype_lyon::prelude::*;
+
+use crate::drawing;
+use crate::history::Edit;
+use crate::model::{self, Interface, SystemNode, Throughput};
+use crate::selection::Selected;
+
+/// Keeps interfaces on their system's boundary, and lets the user slide the selected ones along it.
+/// The inspector sets how much an interface lets through, and how long that takes.
+pub struct InterfacePlugin;
+
+impl Plugin for InterfacePlugin {
+        fn build(&self, app: &mut App) {
+                app.add_systems(Update, (
+                        (
+                                update_circum_points,
+                                slide_interface,
+                                update_interface,
+                        ).chain(),
+                ));
+        }
+}
+
+const CIRCUM_SPACING: f32 = 10.;     // distance along the boundary between circum points
+const MIN_CIRCUM_POINTS: usize = 36; // so small systems still have a useful number of spots
+
+/// How fast the arrow keys slide an interface around its system, in radians per second.
+const SLIDE_SPEED: f32 = PI / 4.;
+
+/// Points sampled along a system's boundary, which its interfaces snap to.
+#[derive(Component, Debug, Clone, Default)]
+pub struct CircumPoints {
+        pub centre: Vec2,
+        pub points: Vec<Vec2>,
+}
+
+impl CircumPoints {
+        /// Samples the boundary of the circle around `centre`, about `CIRCUM_SPACING` apart.
+        pub fn new(centre: Vec2, radius: f32) -> Self {
+                let count = ((TAU * radius / CIRCUM_SPACING).round() as usize).max(MIN_CIRCUM_POINTS);
+                let points = (0..count)
+                        .map(|i| centre + Vec2::from_angle(i as f32 * TAU / count as f32) * radius)
+                        .collect();
+                Self { centre, points }
+        }
+
+        /// Snaps a boundary angle to the nearest circum point.
+        pub fn snap_angle(&self, angle: f32) -> f32 {
+                let radius = self.points.first().map_or(0., |p| p.distance(self.centre));
+                match find_closest_point(&self.points, &(self.centre + Vec2::from_angle(angle) * radius)) {
+                        Some(closest) => model::angle_around(self.centre, closest),
+                        None => angle,
+                }
+        }
+}
+
+/// The little dots marking a system's circum points (and its centre).
+#[derive(Component)]
+struct CircumDot;
+
+/// Systems that moved or changed size since the last frame.
+type ChangedSystems<'w, 's> = Query<'w, 's,
+        (Entity, Ref<'static, SystemNode>, &'static Transform, Option<&'static Children>),
+        Or<(Changed<SystemNode>, Changed<Transform>)>,
+>;
+
+/// Resamples a system's circum points whenever it moves or changes size.
+fn update_circum_points(
+        mut commands: Commands,
+        q_systems: ChangedSystems,
+        q_dots: Query<(), With<CircumDot>>,
+) {
+        for (entity, system, transform, children) in &q_systems {
+                let centre = transform.translation.xy();
+                let cp = CircumPoints::new(centre, system.radius);
+
+                // the dots are children of the system, so they follow it around on their own
+                // and only need redrawing when it's resized
+                if system.is_changed() {
+                        for &child in children.into_iter().flatten() {
+                                if q_dots.contains(child) {
+                                        commands.entity(child).despawn_recursive();
+                                }
+                        }
+                        let dots: Vec<Vec2> = cp.points.iter().map(|p| *p - centre).chain([Vec2::ZERO]).collect();
+                        commands.entity(entity).with_children(|parent| {
+                                for dot in dots {
+                                        parent.spawn((
+                                                ShapeBundle {
+                                                        path: GeometryBuilder::build_as(&shapes::Circle {
+                                                                radius: 2.0,
+                                                                center: dot,
+                                                        }),
+                                                        spatial: SpatialBundle {
+                                                                transform: Transform::from_xyz(0., 0., 4.),
+                                                                ..default()
+                                                        },
+                                                        ..default()
+                                                },
+                                                Stroke::new(Color::BLACK, 1.0),
+                                                Fill::color(Color::CYAN),
+                                                CircumDot,
+                                        ));
+                                }
+                        });
+                }
+                commands.entity(entity).insert(cp);
+        }
+}
+
+fn slide_interface(
+        mut contexts: EguiContexts,
+        mut query: Query<&mut Interface, With<Selected>>,
+        q_circum: Query<&CircumPoints>,
+        keyboard_input: Res<ButtonInput<KeyCode>>,
+        time: Res<Time>,
+        mut edits: EventWriter<Edit>,
+) {
+        // the arrows move the text cursor while typing
+        if contexts.ctx_mut().wants_keyboard_input() {
+                return;
+        }
+        // up slides counter-clockwise, down clockwise
+        let mut movement_factor = 0.;
+        if keyboard_input.pressed(KeyCode::ArrowUp) {
+                movement_factor += 1.;
+        }
+        if keyboard_input.pressed(KeyCode::ArrowDown) {
+                movement_factor -= 1.;
+        }
+
+        let released = keyboard_input.any_just_released([KeyCode::ArrowUp, KeyCode::ArrowDown]);
+        for mut interface in &mut query {
+                if movement_factor != 0. {
+                        interface.angle += movement_factor * SLIDE_SPEED * time.delta_seconds();
+                } else if released {
+                        if let Ok(cp) = q_circum.get(interface.system) {
+                                interface.angle = cp.snap_angle(interface.angle);
+                        }
+                }
+        }
+        if movement_factor == 0. && released && !query.is_empty() {
+                edits.send(Edit("Slide interface".to_string()));
+        }
+}
+
+/// Places every interface on its system's boundary at its angle, facing along the outward normal.
+pub fn update_interface(
+        mut q_interfaces: Query<(&Interface, &mut Transform)>,
+        q_systems: Query<(&Transform, &SystemNode), Without<Interface>>,
+) {
+        for (interface, mut t) in &mut q_interfaces {
+                let Ok((system_transform, system)) = q_systems.get(interface.system) else {
+                        continue;
+                };
+                let target = drawing::interface_transform(system_transform.translation.xy(), system.radius, interface.angle);
+                // `set_if_neq` leaves it untouched unless it moved, so only real moves count as changes
+                t.set_if_neq(target);
+        }
+}
+
+fn find_closest_point(points: &[Vec2], comparison_point: &Vec2) -> Option<Vec2> {
+        points
+            .iter()
+            .min_by(|a, b| {
+                // Calculate the squared distance to avoid sqrt for comparison
+                let distance_a = (comparison_point.x - a.x).powi(2) + (comparison_point.y - a.y).powi(2);
+                let distance_b = (comparison_point.x - b.x).powi(2) + (comparison_point.y - b.y).powi(2);
+
+                distance_a.partial_cmp(&distance_b).unwrap_or(std::cmp::Ordering::Equal)
+            })
+            .copied() // Copy the value to return it, since iter() returns references
+}
+
+/// Edits how much an interface lets through, and how long that takes.
+pub fn throughput_properties(ui: &mut egui::Ui, edits: &mut EventWriter<Edit>, mut throughput: Mut<Throughput>) {
+        let mut edited = *throughput;
+        let mut finished = false;
+        egui::Grid::new("interface throughput").num_columns(2).show(ui, |ui| {
+                let mut limited = edited.capacity.is_some();
+                if ui.checkbox(&mut limited, "Capacity").changed() {
+                        edited.capacity = limited.then_some(1.);
+                        finished = true;
+                }
+                if let Some(capacity) = &mut edited.capacity {
+                        let capacity = ui.add(egui::DragValue::new(capacity)
+                                .clamp_range(0.0..=f32::MAX)
+                                .speed(0.1)
+                                .suffix(" /s"));
+                        finished |= capacity.drag_released() || capacity.lost_focus();
+                } else {
+                        ui.weak("unlimited");
+                }
+                ui.end_row();
+
+                ui.label("Delay");
+                let delay = ui.add(egui::DragValue::new(&mut edited.delay)
+                        .clamp_range(0.0..=f32::MAX)
+                        .speed(0.05)
+                        .suffix(" s"));
+                finished |= delay.drag_released() || delay.lost_focus();
+                ui.end_row();
+        });
+        throughput.set_if_neq(edited);
+        if finished {
+                edits.send(Edit("Change interface throughput".to_string()));
+        }
+}
@@ -0,0 +1,191 @@
+use bevy::prelude::*;
+use bevy::render::camera::RenderTarget;
+use bevy::render::render_resource::{Extent3d, TextureDimension, TextureFormat, TextureUsages};
+use bevy::render::render_asset::RenderAssetUsages;
+use bevy::window::PrimaryWindow;
+use bevy_egui::{egui, EguiContexts};
+
+use crate::helper::MainCamera;
+use crate::selection::HitQuery;
+use crate::toolbar_menu;
+use crate::viewport::{self, CameraTarget};
+
+/// Shows the whole canvas in the bottom left corner, with a rectangle around the part the
+/// window is showing. Dragging the rectangle moves the view; clicking elsewhere glides to it.
+pub struct MinimapPlugin;
+
+impl Plugin for MinimapPlugin {
+        fn build(&self, app: &mut App) {
+                app.add_systems(Startup, setup_minimap)
+                        .add_systems(Update, (frame_minimap, minimap_panel).chain());
+        }
+}
+
+/// Size of the minimap, in points (and pixels of the texture it's rendered to).
+const MAP_WIDTH: f32 = 240.;
+const MAP_HEIGHT: f32 = 160.;
+
+/// How much of the window the minimap takes up from the bottom, frame included; for panels
+/// stacked above it.
+pub const HEIGHT: f32 = MAP_HEIGHT + 35.;
+
+/// Room left around the canvas on the minimap, as a fraction of its size.
+const MAP_MARGIN: f32 = 0.1;
+
+/// The image the minimap camera renders to.
+#[derive(Resource)]
+struct Minimap {
+        image: Handle<Image>,
+}
+
+/// Marks the camera that renders the minimap.
+#[derive(Component)]
+struct MinimapCamera;
+
+fn setup_minimap(
+        mut commands: Commands,
+        mut images: ResMut<Assets<Image>>,
+) {
+        let size = Extent3d {
+                width: MAP_WIDTH as u32,
+                height: MAP_HEIGHT as u32,
+                depth_or_array_layers: 1,
+        };
+        let mut image = Image::new_fill(
+                size,
+                TextureDimension::D2,
+                &[0, 0, 0, 0],
+                TextureFormat::Rgba8UnormSrgb,
+                RenderAssetUsages::default(),
+        );
+        image.texture_descriptor.usage =
+                TextureUsages::TEXTURE_BINDING | TextureUsages::COPY_DST | TextureUsages::RENDER_ATTACHMENT;
+        let image = images.add(image);
+
+        commands.spawn((
+                Camera2dBundle {
+                        camera: Camera {
+                                target: RenderTarget::Image(image.clone()),
+                                // render before the window, so it's ready when egui draws it
+                                order: -1,
+                                ..default()
+                        },
+                        ..default()
+                },
+                MinimapCamera,
+        ));
+        commands.insert_resource(Minimap { image });
+}
+
+/// Where the main camera is looking, kept apart from the minimap camera.
+type MainView<'w, 's> = Query<'w, 's, (&'static Transform, &'static OrthographicProjection), (With<MainCamera>, Without<MinimapCamera>)>;
+
+/// Where the minimap camera is looking, kept apart from the main camera.
+type MinimapView<'w, 's> = Query<'w, 's, (&'static Transform, &'static OrthographicProjection), (With<MinimapCamera>, Without<MainCamera>)>;
+
+/// Points the minimap camera at everything on the canvas, or where the main camera is
+/// looking when there's nothing.
+fn frame_minimap(
+        q_shapes: HitQuery,
+        q_main: MainView,
+        mut q_minimap: Query<(&mut Transform, &mut OrthographicProjection), With<MinimapCamera>>,
+) {
+        let Ok((mut transform, mut projection)) = q_minimap.get_single_mut() else {
+                return;
+        };
+        let (centre, scale) = match viewport::content_bounds(&q_shapes) {
+                Some(bounds) => {
+                        let size = bounds.size() * (1. + MAP_MARGIN);
+                        (bounds.center(), (size.x / MAP_WIDTH).max(size.y / MAP_HEIGHT))
+                }
+                None => match q_main.get_single() {
+                        Ok((main, main_projection)) => (main.translation.xy(), main_projection.scale * 4.),
+                        Err(_) => return,
+                },
+        };
+
+        if transform.translation.xy() != centre {
+                transform.translation = centre.extend(transform.translation.z);
+        }
+        if projection.scale != scale {
+                projection.scale = scale;
+        }
+}
+
+fn minimap_panel(
+        mut contexts: EguiContexts,
+        mut grab: Local<Option<Vec2>>,
+        minimap: Option<Res<Minimap>>,
+        mut target: ResMut<CameraTarget>,
+        q_window: Query<&Window, With<PrimaryWindow>>,
+        q_minimap: MinimapView,
+        mut q_main: Query<(&mut Transform, &OrthographicProjection), With<MainCamera>>,
+) {
+        let Some(minimap) = minimap else {
+                return;
+        };
+        let (Ok(window), Ok((map_transform, map_projection)), Ok((mut main, main_projection))) =
+                (q_window.get_single(), q_minimap.get_single(), q_main.get_single_mut())
+        else {
+                return;
+        };
+        let texture = contexts.add_image(minimap.image.clone_weak());
+
+        egui::Window::new("Minimap")
+                .title_bar(false)
+                .movable(false)
+                .resizable(false)
+                .anchor(egui::Align2::LEFT_BOTTOM, egui::Vec2::ZERO)
+                .frame(toolbar_menu::floating_frame())
+                .show(contexts.ctx_mut(), |ui| {
+                        let (rect, response) = ui.allocate_exact_size(egui::vec2(MAP_WIDTH, MAP_HEIGHT), egui::Sense::click_and_drag());
+                        let painter = ui.painter_at(rect);
+                        painter.image(
+                                texture,
+                                rect,
+                                egui::Rect::from_min_max(egui::pos2(0., 0.), egui::pos2(1., 1.)),
+                                egui::Color32::WHITE,
+                        );
+
+                        // between points on the minimap and the world; y runs the other way
+                        let map_centre = map_transform.translation.xy();
+                        let to_world = |pos: egui::Pos2| {
+                                let offset = pos - rect.center();
+                                map_centre + Vec2::new(offset.x, -offset.y) * map_projection.scale
+                        };
+                        let to_map = |point: Vec2| {
+                                let offset = (point - map_centre) / map_projection.scale;
+                                rect.center() + egui::vec2(offset.x, -offset.y)
+                        };
+
+                        let view_centre = main.translation.xy();
+                        let half = Vec2::new(window.width(), window.height()) * main_projection.scale / 2.;
+                        let view = egui::Rect::from_two_pos(to_map(view_centre - half), to_map(view_centre + half));
+                        painter.rect_stroke(view, 0., egui::Stroke::new(1.5, egui::Color32::from_rgb(30, 110, 230)));
+
+                        let pointer = response.interact_pointer_pos();
+                        if response.drag_started() {
+                                // dragging the rectangle moves the view along with it
+                                *grab = pointer
+                                        .filter(|pos| view.contains(*pos))
+                                        .map(|pos| view_centre - to_world(pos));
+                        }
+                        match (pointer, *grab) {
+                                (Some(pos), Some(offset)) if response.dragged() => {
+                                        let centre = to_world(pos) + offset;
+                                        if centre != view_centre {
+                                                main.translation = centre.extend(main.translation.z);
+                                                target.hold();
+                                        }
+                                        ui.ctx().set_cursor_icon(egui::CursorIcon::Grabbing);
+                                }
+                                (Some(pos), None) if response.clicked() || response.dragged() => {
+                                        target.look_at(to_world(pos));
+                                }
+                                _ => {}
+                        }
+                        if response.drag_released() {
+                                *grab = None;
+                        }
+                });
+}
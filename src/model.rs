@@ -0,0 +1,355 @@
+use bevy::prelude::*;
+use serde::{Deserialize, Serialize};
+
+/// Registers the resources backing the system model.
+///
+/// Every shape drawn on the canvas carries one of the element components below
+/// (`SystemNode`, `Flow`, `Interface`, `Source`, `Sink`, `Disruption`) together
+/// with an `ElementId` and a `Name`, so the diagram can be queried as a model
+/// instead of a pile of lyon paths.
+pub struct ModelPlugin;
+
+impl Plugin for ModelPlugin {
+        fn build(&self, app: &mut App) {
+                app.init_resource::<ElementIds>();
+        }
+}
+
+/// Stable identity of a diagram element.
+/// Unlike `Entity` it is ours to hand out, so it can be written to disk and read back.
+#[derive(Component, Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct ElementId(pub u64);
+
+/// Allocator for `ElementId`s.
+#[derive(Resource, Debug, Default)]
+pub struct ElementIds {
+        next: u64,
+}
+
+impl ElementIds {
+        /// Returns a fresh id that has not been handed out before.
+        pub fn next(&mut self) -> ElementId {
+                self.next += 1;
+                ElementId(self.next)
+        }
+
+        /// Makes sure `id` (e.g. one read from a file) is never handed out again.
+        pub fn reserve(&mut self, id: ElementId) {
+                self.next = self.next.max(id.0);
+        }
+}
+
+/// The kinds of elements that make up a model, in the order the toolbar lists them.
+/// Also stored on every element, for code that treats all kinds alike.
+#[derive(Component, Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ElementKind {
+        System,
+        Flow,
+        Interface,
+        Source,
+        Sink,
+        Disruption,
+}
+
+impl ElementKind {
+        pub const ALL: [ElementKind; 6] = [
+                ElementKind::System,
+                ElementKind::Flow,
+                ElementKind::Interface,
+                ElementKind::Source,
+                ElementKind::Sink,
+                ElementKind::Disruption,
+        ];
+
+        pub fn label(&self) -> &'static str {
+                match self {
+                        ElementKind::System     => "System",
+                        ElementKind::Flow       => "Flow",
+                        ElementKind::Interface  => "Interface",
+                        ElementKind::Source     => "Source",
+                        ElementKind::Sink       => "Sink",
+                        ElementKind::Disruption => "Disruption",
+                }
+        }
+}
+
+/// Identity, kind, display name and notes shared by every element.
+#[derive(Bundle)]
+pub struct ElementBundle {
+        pub id: ElementId,
+        pub name: Name,
+        pub description: Description,
+        pub kind: ElementKind,
+}
+
+impl ElementBundle {
+        /// Allocates a new id and names the element after its kind, e.g. "Sink 3".
+        pub fn new(ids: &mut ElementIds, kind: ElementKind) -> Self {
+                let id = ids.next();
+                Self {
+                        id,
+                        name: Name::new(format!("{} {}", kind.label(), id.0)),
+                        description: Description::default(),
+                        kind,
+                }
+        }
+}
+
+/// Free-form notes on an element, edited in the inspector.
+#[derive(Component, Debug, Clone, Default, PartialEq)]
+pub struct Description(pub String);
+
+/// A system, drawn as a circle centred on the entity's translation.
+#[derive(Component, Debug, Clone, Copy)]
+pub struct SystemNode {
+        pub radius: f32,
+}
+
+/// Marks a system as a subsystem living inside another system.
+#[derive(Component, Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ParentSystem(pub Entity);
+
+/// Returns the systems `system` is nested in, innermost first.
+pub fn ancestors(system: Entity, q_parents: &Query<&ParentSystem>) -> Vec<Entity> {
+        let mut ancestors = Vec::new();
+        let mut current = system;
+        while let Ok(parent) = q_parents.get(current) {
+                // guard against cycles, which would otherwise loop forever
+                if parent.0 == system || ancestors.contains(&parent.0) {
+                        break;
+                }
+                ancestors.push(parent.0);
+                current = parent.0;
+        }
+        ancestors
+}
+
+/// Is `system` nested (at any depth) in `ancestor`?
+pub fn is_descendant(system: Entity, ancestor: Entity, q_parents: &Query<&ParentSystem>) -> bool {
+        ancestors(system, q_parents).contains(&ancestor)
+}
+
+/// A point on a system's boundary through which flows enter or leave.
+#[derive(Component, Debug, Clone, Copy)]
+pub struct Interface {
+        /// The system whose boundary the interface sits on.
+        pub system: Entity,
+        /// Position on the boundary, in radians counter-clockwise from +X.
+        pub angle: f32,
+}
+
+/// How much an interface lets through: at most `capacity` per second (no limit if `None`),
+/// with everything taking `delay` seconds to get across.
+#[derive(Component, Debug, Clone, Copy, PartialEq, Default)]
+pub struct Throughput {
+        pub capacity: Option<f32>,
+        pub delay: f32,
+}
+
+/// A directed flow between two elements (sources, sinks, interfaces or systems).
+#[derive(Component, Debug, Clone, Copy)]
+pub struct Flow {
+        pub start: Entity,
+        pub end: Entity,
+}
+
+/// The curve a flow is drawn along, in world coordinates: a quadratic bezier through `ctrl`,
+/// or a cubic one through `ctrl` and then `ctrl2`.
+#[derive(Component, Debug, Clone, Copy, PartialEq)]
+pub struct FlowCurve {
+        pub from: Vec2,
+        pub ctrl: Vec2,
+        pub ctrl2: Option<Vec2>,
+        pub to: Vec2,
+}
+
+impl FlowCurve {
+        pub fn quadratic(from: Vec2, ctrl: Vec2, to: Vec2) -> Self {
+                Self { from, ctrl, ctrl2: None, to }
+        }
+
+        pub fn is_cubic(&self) -> bool {
+                self.ctrl2.is_some()
+        }
+
+        /// The control points, in order.
+        pub fn controls(&self) -> impl Iterator<Item = Vec2> {
+                std::iter::once(self.ctrl).chain(self.ctrl2)
+        }
+
+        /// The `index`th control point, as numbered by `controls`.
+        pub fn control_mut(&mut self, index: usize) -> Option<&mut Vec2> {
+                match index {
+                        0 => Some(&mut self.ctrl),
+                        1 => self.ctrl2.as_mut(),
+                        _ => None,
+                }
+        }
+
+        /// The point at `t`, from 0 at `from` to 1 at `to`.
+        pub fn point_at(&self, t: f32) -> Vec2 {
+                let s = 1. - t;
+                match self.ctrl2 {
+                        None => s * s * self.from + 2. * s * t * self.ctrl + t * t * self.to,
+                        Some(ctrl2) => s * s * s * self.from
+                                + 3. * s * s * t * self.ctrl
+                                + 3. * s * t * t * ctrl2
+                                + t * t * t * self.to,
+                }
+        }
+
+        /// The direction the curve arrives at `to` in.
+        pub fn end_heading(&self) -> Vec2 {
+                let last = self.ctrl2.unwrap_or(self.ctrl);
+                (self.to - last).try_normalize()
+                        .or((self.to - self.ctrl).try_normalize())
+                        .or((self.to - self.from).try_normalize())
+                        .unwrap_or(Vec2::X)
+        }
+
+        /// The same curve as a cubic; exact, since a quadratic is just a special cubic.
+        pub fn to_cubic(self) -> Self {
+                match self.ctrl2 {
+                        Some(_) => self,
+                        None => Self {
+                                ctrl: self.from + (self.ctrl - self.from) * 2. / 3.,
+                                ctrl2: Some(self.to + (self.ctrl - self.to) * 2. / 3.),
+                                ..self
+                        },
+                }
+        }
+
+        /// The closest quadratic to this curve; exact if it was made by `to_cubic`.
+        pub fn to_quadratic(self) -> Self {
+                match self.ctrl2 {
+                        None => self,
+                        Some(ctrl2) => Self::quadratic(
+                                self.from,
+                                (3. * (self.ctrl + ctrl2) - self.from - self.to) / 4.,
+                                self.to,
+                        ),
+                }
+        }
+}
+
+/// What a flow carries.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default, Serialize, Deserialize)]
+pub enum Substance {
+        #[default]
+        Matter,
+        Energy,
+        Information,
+}
+
+impl Substance {
+        pub const ALL: [Substance; 3] = [Substance::Matter, Substance::Energy, Substance::Information];
+
+        pub fn label(&self) -> &'static str {
+                match self {
+                        Substance::Matter      => "Matter",
+                        Substance::Energy      => "Energy",
+                        Substance::Information => "Information",
+                }
+        }
+
+        /// The unit a new flow of this substance is measured in.
+        pub fn default_unit(&self) -> &'static str {
+                match self {
+                        Substance::Matter      => "kg/s",
+                        Substance::Energy      => "W",
+                        Substance::Information => "bit/s",
+                }
+        }
+}
+
+/// How much of what a flow carries: `rate` of `substance`, measured in `unit`.
+#[derive(Component, Debug, Clone, PartialEq)]
+pub struct FlowQuantity {
+        pub substance: Substance,
+        pub unit: String,
+        pub rate: f32,
+}
+
+impl Default for FlowQuantity {
+        fn default() -> Self {
+                Self {
+                        substance: Substance::default(),
+                        unit: Substance::default().default_unit().to_string(),
+                        rate: 1.,
+                }
+        }
+}
+
+/// Marks a flow whose control points are placed automatically, bending it around any systems
+/// that are in the way, rather than by hand.
+#[derive(Component, Debug, Clone, Copy, Default)]
+pub struct AutoRoute;
+
+/// Where a flow originates, outside of any system.
+#[derive(Component, Debug, Clone, Copy, Default)]
+pub struct Source;
+
+/// Where a flow ends up, outside of any system.
+#[derive(Component, Debug, Clone, Copy, Default)]
+pub struct Sink;
+
+/// Something that perturbs the model, optionally attached to another element
+/// (a flow, an interface or a system).
+#[derive(Component, Debug, Clone, Copy, PartialEq, Default)]
+pub struct Disruption {
+        pub target: Option<Entity>,
+        pub perturbation: Perturbation,
+        pub schedule: DisruptionSchedule,
+}
+
+/// What a disruption does to what it's attached to while it's in effect.
+#[derive(Debug, Clone, Copy, PartialEq, Default, Serialize, Deserialize)]
+pub enum Perturbation {
+        /// Nothing gets through.
+        #[default]
+        CutFlow,
+        /// Only this fraction of what would get through does.
+        ReduceCapacity { to: f32 },
+        /// This many times as much is asked for as usual.
+        SpikeInput { by: f32 },
+}
+
+impl Perturbation {
+        pub fn label(&self) -> &'static str {
+                match self {
+                        Perturbation::CutFlow              => "Cut flow",
+                        Perturbation::ReduceCapacity { .. } => "Reduce capacity",
+                        Perturbation::SpikeInput { .. }     => "Spike input",
+                }
+        }
+}
+
+/// When a disruption is in effect, in simulated seconds.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub enum DisruptionSchedule {
+        /// Once, from `start` on, for `duration`.
+        At { start: f32, duration: f32 },
+        /// At random, `rate` times a second on average, for `duration` each time.
+        Random { rate: f32, duration: f32 },
+}
+
+impl Default for DisruptionSchedule {
+        fn default() -> Self {
+                DisruptionSchedule::At { start: 5., duration: 5. }
+        }
+}
+
+/// Returns the smallest of `systems` (entity, centre, radius) containing `point`, i.e. the one
+/// whatever is at `point` sits in.
+pub fn innermost_system(point: Vec2, systems: impl IntoIterator<Item = (Entity, Vec2, f32)>) -> Option<(Entity, Vec2, f32)> {
+        systems
+                .into_iter()
+                .filter(|(_, centre, radius)| centre.distance(point) < *radius)
+                .min_by(|a, b| a.2.total_cmp(&b.2))
+}
+
+/// Returns the angle of `point` around `centre`, in the convention used by `Interface::angle`.
+pub fn angle_around(centre: Vec2, point: Vec2) -> f32 {
+        let d = point - centre;
+        d.y.atan2(d.x)
+}
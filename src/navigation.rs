@@ -0,0 +1,209 @@
+use std::collections::HashSet;
+use bevy::prelude::*;
+use bevy::window::PrimaryWindow;
+use bevy_egui::EguiContexts;
+
+use crate::helper::{self, MainCamera, MyWorldCoords};
+use crate::model::{self, ElementKind, Flow, Interface, ParentSystem, SystemNode};
+use crate::toolbar_menu::{self, ActiveTool};
+use crate::viewport::CameraTarget;
+
+/// Lets the user drill down into a system by double-clicking it, so the canvas only shows
+/// that system and what's inside it, and climb back up through a breadcrumb bar.
+pub struct NavigationPlugin;
+
+impl Plugin for NavigationPlugin {
+        fn build(&self, app: &mut App) {
+                app.init_resource::<ViewRoot>()
+                        .add_systems(Update, (
+                                zoom_into_system.after(helper::update_cursor_position),
+                                breadcrumb_bar,
+                                frame_view_root,
+                                hide_outside_view_root,
+                        ).chain());
+        }
+}
+
+/// Room left around a system when zooming into it, as a fraction of its radius.
+const FRAME_MARGIN: f32 = 0.15;
+
+/// Two clicks closer together than this, in seconds, make a double click.
+const DOUBLE_CLICK_TIME: f32 = 0.3;
+
+/// The system the canvas is showing, or `None` for the whole diagram.
+#[derive(Resource, Default)]
+pub struct ViewRoot {
+        pub system: Option<Entity>,
+        /// Where the camera was (translation and scale) before drilling into a system,
+        /// so going back to the top level puts it back there.
+        canvas_view: Option<(Vec3, f32)>,
+}
+
+/// A double click on a system (with the select tool) makes it the view root.
+#[allow(clippy::too_many_arguments)]
+fn zoom_into_system(
+        mut contexts: EguiContexts,
+        mut last_click: Local<Option<f32>>,
+        time: Res<Time>,
+        mouse_button_input: Res<ButtonInput<MouseButton>>,
+        active_tool: Res<State<ActiveTool>>,
+        cursor: Res<MyWorldCoords>,
+        mut root: ResMut<ViewRoot>,
+        q_systems: Query<(Entity, &SystemNode, &GlobalTransform, &InheritedVisibility)>,
+) {
+        if !mouse_button_input.just_pressed(MouseButton::Left)
+                || *active_tool.get() != ActiveTool::Select
+                || contexts.ctx_mut().is_pointer_over_area()
+        {
+                return;
+        }
+        let now = time.elapsed_seconds();
+        let double_click = last_click.is_some_and(|last| now - last <= DOUBLE_CLICK_TIME);
+        *last_click = if double_click { None } else { Some(now) };
+        if !double_click {
+                return;
+        }
+
+        // the smallest visible system under the cursor
+        let clicked = q_systems
+                .iter()
+                .filter(|(.., visibility)| visibility.get())
+                .filter(|(_, system, transform, _)| transform.translation().xy().distance(cursor.0) < system.radius)
+                .min_by(|a, b| a.1.radius.total_cmp(&b.1.radius))
+                .map(|(entity, ..)| entity);
+        if clicked.is_some() && clicked != root.system {
+                root.system = clicked;
+        }
+}
+
+/// Shows where the view root sits in the hierarchy, and jumps back up to any of its ancestors.
+fn breadcrumb_bar(
+        mut contexts: EguiContexts,
+        mut root: ResMut<ViewRoot>,
+        q_names: Query<&Name>,
+        q_parents: Query<&ParentSystem>,
+) {
+        let Some(system) = root.system else {
+                return;
+        };
+        let mut trail = model::ancestors(system, &q_parents);
+        trail.reverse();
+        let label = |entity: Entity| q_names.get(entity).map_or_else(|_| "System".to_string(), |name| name.to_string());
+
+        let mut target = None;
+        egui::Window::new("Breadcrumbs")
+                .title_bar(false)
+                .movable(false)
+                .resizable(false)
+                .anchor(egui::Align2::LEFT_TOP, egui::Vec2::ZERO)
+                .frame(toolbar_menu::floating_frame())
+                .show(contexts.ctx_mut(), |ui| {
+                        ui.horizontal(|ui| {
+                                if ui.link("Canvas").clicked() {
+                                        target = Some(None);
+                                }
+                                for &ancestor in &trail {
+                                        ui.label("›");
+                                        if ui.link(label(ancestor)).clicked() {
+                                                target = Some(Some(ancestor));
+                                        }
+                                }
+                                ui.label("›");
+                                ui.strong(label(system));
+                        });
+                });
+        if let Some(target) = target {
+                root.system = target;
+        }
+}
+
+/// Points the main camera at the view root whenever it changes.
+fn frame_view_root(
+        mut root: ResMut<ViewRoot>,
+        mut target: ResMut<CameraTarget>,
+        q_window: Query<&Window, With<PrimaryWindow>>,
+        q_systems: Query<(&SystemNode, &GlobalTransform)>,
+        q_camera: Query<(&Transform, &OrthographicProjection), With<MainCamera>>,
+) {
+        // the root may have been deleted from under us
+        if let Some(system) = root.system {
+                if !q_systems.contains(system) {
+                        root.system = None;
+                }
+        }
+        if !root.is_changed() {
+                return;
+        }
+        let Ok((transform, projection)) = q_camera.get_single() else {
+                return;
+        };
+
+        match root.system.and_then(|system| q_systems.get(system).ok()) {
+                Some((system, system_transform)) => {
+                        if root.canvas_view.is_none() {
+                                root.canvas_view = Some((transform.translation, projection.scale));
+                        }
+                        let Ok(window) = q_window.get_single() else {
+                                return;
+                        };
+                        let size = 2. * system.radius * (1. + FRAME_MARGIN);
+                        target.frame(system_transform.translation().xy(), size / window.width().min(window.height()).max(1.));
+                }
+                None => {
+                        if let Some((translation, scale)) = root.canvas_view.take() {
+                                target.frame(translation.xy(), scale);
+                        }
+                }
+        }
+}
+
+/// Every element, with what's needed to tell whether it's inside the view root.
+type ViewElements<'w, 's> = Query<'w, 's, (
+        Entity,
+        &'static ElementKind,
+        &'static GlobalTransform,
+        &'static mut Visibility,
+        Option<&'static Interface>,
+        Option<&'static Flow>,
+)>;
+
+/// Hides everything outside the view root: systems that aren't it or nested in it, their
+/// interfaces, basins and disruptions outside its circle, and flows with a hidden end.
+fn hide_outside_view_root(
+        root: Res<ViewRoot>,
+        q_systems: Query<(&SystemNode, &GlobalTransform)>,
+        q_parents: Query<&ParentSystem>,
+        mut q_elements: ViewElements,
+) {
+        let root = root.system.and_then(|system| {
+                let (node, transform) = q_systems.get(system).ok()?;
+                Some((system, transform.translation().xy(), node.radius))
+        });
+        let in_view = |system: Entity| match root {
+                Some((root, ..)) => system == root || model::is_descendant(system, root, &q_parents),
+                None => true,
+        };
+
+        let shown: HashSet<Entity> = q_elements
+                .iter()
+                .filter(|(entity, kind, transform, _, interface, _)| match (kind, root) {
+                        (_, None) => true,
+                        (ElementKind::Flow, _) => false,
+                        (ElementKind::System, _) => in_view(*entity),
+                        (ElementKind::Interface, _) => interface.is_some_and(|i| in_view(i.system)),
+                        (_, Some((_, centre, radius))) => transform.translation().xy().distance(centre) <= radius,
+                })
+                .map(|(entity, ..)| entity)
+                .collect();
+
+        for (entity, _, _, mut visibility, _, flow) in &mut q_elements {
+                let visible = match flow {
+                        Some(flow) => shown.contains(&flow.start) && shown.contains(&flow.end),
+                        None => shown.contains(&entity),
+                };
+                let wanted = if visible { Visibility::Inherited } else { Visibility::Hidden };
+                if *visibility != wanted {
+                        *visibility = wanted;
+                }
+        }
+}
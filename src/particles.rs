@@ -0,0 +1,177 @@
+use std::collections::{HashMap, HashSet};
+use bevy::prelude::*;
+use bevy_prototype_lyon::prelude::*;
+
+use crate::drawing;
+use crate::flow;
+use crate::model::{FlowCurve, FlowQuantity};
+use crate::simulation::{FlowState, Simulation};
+
+/// Shows the simulation running: dots travel along every flow that carries anything, as fast
+/// as its rate. The dots are kept in a pool and handed from flow to flow as needed, rather
+/// than spawned and despawned, so there can be a great many of them.
+pub struct ParticlesPlugin;
+
+impl Plugin for ParticlesPlugin {
+        fn build(&self, app: &mut App) {
+                app.init_resource::<Particles>()
+                        .add_systems(Update, animate_particles);
+        }
+}
+
+const DOT_RADIUS: f32 = 4.;
+/// Distance between dots along a flow, in world units.
+const DOT_SPACING: f32 = 40.;
+/// So long flows don't take every dot there is.
+const MAX_DOTS_PER_FLOW: usize = 64;
+/// Drawn just above the flows they travel along.
+const DOT_DEPTH: f32 = drawing::FLOW_DEPTH + 0.5;
+
+/// How fast dots travel for each unit of rate, in world units per simulated second...
+const DOT_SPEED: f32 = 40.;
+/// ...up to this fast, beyond which they'd only flicker.
+const MAX_DOT_SPEED: f32 = 600.;
+
+/// How many points along a flow are measured to space its dots evenly.
+const ARC_SAMPLES: usize = 24;
+
+#[derive(Component, Debug, Clone, Copy, Default)]
+struct Particle;
+
+/// The dots on each flow, and those not on any.
+#[derive(Resource, Default)]
+struct Particles {
+        pool: Vec<Entity>,
+        streams: HashMap<Entity, Stream>,
+}
+
+/// The dots travelling along one flow.
+#[derive(Default)]
+struct Stream {
+        /// How far the first dot is along the flow, between 0 and `DOT_SPACING`.
+        travelled: f32,
+        dots: Vec<Entity>,
+}
+
+/// Distances along `curve` at `ARC_SAMPLES` evenly spread values of t, with the points there.
+fn measure(curve: &FlowCurve) -> Vec<(f32, Vec2)> {
+        let mut length = 0.;
+        let mut last = curve.from;
+        (0..=ARC_SAMPLES)
+                .map(|i| {
+                        let point = curve.point_at(i as f32 / ARC_SAMPLES as f32);
+                        length += last.distance(point);
+                        last = point;
+                        (length, point)
+                })
+                .collect()
+}
+
+/// The point `distance` along a curve measured by `measure`.
+fn point_along(samples: &[(f32, Vec2)], distance: f32) -> Vec2 {
+        let after = samples.partition_point(|(length, _)| *length < distance).clamp(1, samples.len() - 1);
+        let ((start, a), (end, b)) = (samples[after - 1], samples[after]);
+        let t = if end > start { (distance - start) / (end - start) } else { 0. };
+        a.lerp(b, t.clamp(0., 1.))
+}
+
+type Dots<'w, 's> = Query<'w, 's, (&'static mut Transform, &'static mut Visibility, &'static mut Fill), With<Particle>>;
+
+/// Hides `dot` and puts it back in the pool.
+fn release(dot: Entity, pool: &mut Vec<Entity>, q_dots: &mut Dots) {
+        if let Ok((_, mut visibility, _)) = q_dots.get_mut(dot) {
+                *visibility = Visibility::Hidden;
+        }
+        pool.push(dot);
+}
+
+fn animate_particles(
+        mut commands: Commands,
+        time: Res<Time>,
+        simulation: Res<Simulation>,
+        mut particles: ResMut<Particles>,
+        q_flows: Query<(Entity, &FlowCurve, &FlowQuantity, &FlowState, &InheritedVisibility)>,
+        mut q_dots: Dots,
+) {
+        let Particles { pool, streams } = &mut *particles;
+        let mut flowing = HashSet::new();
+
+        for (entity, curve, quantity, state, visibility) in &q_flows {
+                if simulation.time <= 0. || state.rate <= 0. || !visibility.get() {
+                        continue;
+                }
+                flowing.insert(entity);
+                let stream = streams.entry(entity).or_default();
+
+                let samples = measure(curve);
+                let length = samples.last().map_or(0., |(length, _)| *length);
+                let count = ((length / DOT_SPACING).ceil() as usize).clamp(1, MAX_DOTS_PER_FLOW);
+                while stream.dots.len() > count {
+                        if let Some(dot) = stream.dots.pop() {
+                                release(dot, pool, &mut q_dots);
+                        }
+                }
+                if simulation.running {
+                        let speed = (state.rate * DOT_SPEED).min(MAX_DOT_SPEED);
+                        stream.travelled = (stream.travelled + speed * simulation.speed * time.delta_seconds()) % DOT_SPACING;
+                }
+
+                let color = flow::substance_color(quantity.substance);
+                for i in 0..count {
+                        let distance = stream.travelled + i as f32 * DOT_SPACING;
+                        let position = point_along(&samples, distance).extend(DOT_DEPTH);
+                        let shown = if distance <= length { Visibility::Inherited } else { Visibility::Hidden };
+
+                        if let Some(&dot) = stream.dots.get(i) {
+                                if let Ok((mut transform, mut visibility, mut fill)) = q_dots.get_mut(dot) {
+                                        if transform.translation != position {
+                                                transform.translation = position;
+                                        }
+                                        if *visibility != shown {
+                                                *visibility = shown;
+                                        }
+                                        if fill.color != color {
+                                                fill.color = color;
+                                        }
+                                }
+                        } else if let Some(dot) = pool.pop() {
+                                if let Ok((mut transform, mut visibility, mut fill)) = q_dots.get_mut(dot) {
+                                        transform.translation = position;
+                                        *visibility = shown;
+                                        fill.color = color;
+                                }
+                                stream.dots.push(dot);
+                        } else {
+                                let dot = commands.spawn((
+                                        ShapeBundle {
+                                                path: GeometryBuilder::build_as(&shapes::Circle {
+                                                        radius: DOT_RADIUS,
+                                                        center: Vec2::ZERO,
+                                                }),
+                                                spatial: SpatialBundle {
+                                                        transform: Transform::from_translation(position),
+                                                        visibility: shown,
+                                                        ..default()
+                                                },
+                                                ..default()
+                                        },
+                                        Fill::color(color),
+                                        Stroke::new(Color::WHITE, 1.),
+                                        Particle,
+                                )).id();
+                                stream.dots.push(dot);
+                        }
+                }
+        }
+
+        // flows that stopped, or are gone, give their dots back
+        streams.retain(|entity, stream| {
+                if flowing.contains(entity) {
+                        return true;
+                }
+                for dot in stream.dots.drain(..) {
+                        release(dot, pool, &mut q_dots);
+                }
+                false
+        });
+}
@@ -0,0 +1,258 @@
+use std::f32::consts::PI;
+use bevy::prelude::*;
+use bevy_egui::EguiContexts;
+
+use crate::align::{self, PlacementPoint};
+use crate::drawing;
+use crate::helper::MyWorldCoords;
+use crate::history::Edit;
+use crate::interface::CircumPoints;
+use crate::model::{self, ElementIds, ElementKind, Flow, FlowCurve, ParentSystem, SystemNode};
+use crate::selection::{self, HitQuery};
+use crate::system_node::MIN_SYSTEM_RADIUS;
+use crate::toolbar_menu::ActiveTool;
+use crate::viewport;
+
+/// Places elements on the canvas with whatever tool is active in the toolbar.
+pub struct PlacementPlugin;
+
+impl Plugin for PlacementPlugin {
+        fn build(&self, app: &mut App) {
+                app.init_resource::<FlowDraft>()
+                        .add_systems(Update, place_with_active_tool.after(align::guide_placement));
+        }
+}
+
+/// How close (in world units) a click has to be to a sink, source, interface or
+/// disruption to pick it.
+const PICK_RADIUS: f32 = 80.;
+
+/// How close (in world units) a disruption has to be placed to a flow's line to be attached to it.
+const TARGET_TOLERANCE: f32 = 10.;
+
+/// Two elements of the same kind closer than this are considered to be in the same spot.
+const DUPLICATE_TOLERANCE: f32 = 5.;
+
+/// Radius given to systems placed from the toolbar.
+pub const DEFAULT_SYSTEM_RADIUS: f32 = 300.;
+
+/// Subsystems are at most this fraction of their parent's radius.
+const SUBSYSTEM_SCALE: f32 = 1. / 3.;
+
+/// A flow is placed with two clicks: this holds the element (and the point on it)
+/// picked by the first one.
+#[derive(Resource, Default)]
+struct FlowDraft(Option<(Entity, Vec2)>);
+
+/// Every element that has a position of its own, i.e. everything but flows.
+/// Elements hidden by drilling into a system can't be clicked, so they are skipped by the helpers below.
+type ElementQuery<'w, 's> = Query<'w, 's, (
+        Entity,
+        &'static ElementKind,
+        &'static GlobalTransform,
+        Option<&'static SystemNode>,
+        &'static InheritedVisibility,
+), Without<Flow>>;
+
+#[allow(clippy::too_many_arguments)]
+fn place_with_active_tool(
+        mut commands: Commands,
+        mut contexts: EguiContexts,
+        mouse_button_input: Res<ButtonInput<MouseButton>>,
+        keyboard_input: Res<ButtonInput<KeyCode>>,
+        active_tool: Res<State<ActiveTool>>,
+        cursor: Res<MyWorldCoords>,
+        point: Res<PlacementPoint>,
+        mut ids: ResMut<ElementIds>,
+        mut flow_draft: ResMut<FlowDraft>,
+        mut edits: EventWriter<Edit>,
+        q_circum: Query<&CircumPoints>,
+        q_elements: ElementQuery,
+        q_flows: Query<&Flow>,
+        q_parents: Query<&ParentSystem>,
+        q_kinds: Query<&ElementKind>,
+        q_shapes: HitQuery,
+) {
+        let ActiveTool::Place(kind) = *active_tool.get() else {
+                flow_draft.0 = None;
+                return;
+        };
+        if kind != ElementKind::Flow {
+                flow_draft.0 = None;
+        }
+
+        // with Space held the left button pans instead
+        if !mouse_button_input.just_pressed(MouseButton::Left) || viewport::space_held(&keyboard_input) {
+                return;
+        }
+        // clicks on the toolbar (or any other egui window) are not meant for the canvas
+        let ctx = contexts.ctx_mut();
+        if ctx.is_pointer_over_area() || ctx.wants_pointer_input() {
+                return;
+        }
+
+        let position = point.0;
+        let added = Edit(format!("Add {}", kind.label().to_lowercase()));
+        match kind {
+                ElementKind::System => {
+                        if is_occupied(kind, position, &q_elements) {
+                                return;
+                        }
+                        // a system placed inside another one becomes its subsystem, and has to fit inside it
+                        let Some((parent, centre, radius)) = innermost_system(position, &q_elements) else {
+                                drawing::spawn_system(&mut commands, &mut ids, position, DEFAULT_SYSTEM_RADIUS, 0);
+                                edits.send(added);
+                                return;
+                        };
+                        let room = radius - centre.distance(position);
+                        let radius = DEFAULT_SYSTEM_RADIUS.min(radius * SUBSYSTEM_SCALE).min(room);
+                        if radius < MIN_SYSTEM_RADIUS {
+                                return;
+                        }
+                        let level = model::ancestors(parent, &q_parents).len() + 1;
+                        let system = drawing::spawn_system(&mut commands, &mut ids, position, radius, level);
+                        commands.entity(system).insert(ParentSystem(parent));
+                        edits.send(added);
+                }
+                ElementKind::Sink | ElementKind::Source => {
+                        if is_occupied(kind, position, &q_elements) {
+                                return;
+                        }
+                        let system = nearest_system(position, &q_elements);
+
+                        // open towards the nearest system, or towards where one would usually be
+                        let facing = match system {
+                                Some((_, centre, _)) => model::angle_around(position, centre),
+                                None if kind == ElementKind::Sink => PI,
+                                None => 0.,
+                        };
+                        let element = if kind == ElementKind::Sink {
+                                drawing::spawn_sink(&mut commands, &mut ids, position, facing)
+                        } else {
+                                drawing::spawn_source(&mut commands, &mut ids, position, facing)
+                        };
+                        // the connecting flow below is part of the same edit
+                        edits.send(added);
+
+                        // connect it to that system with a flow running between the boundary and the opening
+                        let Some((system, centre, radius)) = system else {
+                                return;
+                        };
+                        if centre.distance(position) <= radius + drawing::BASIN_WALL {
+                                return;
+                        }
+                        let mouth = position + Vec2::from_angle(facing) * drawing::BASIN_WALL;
+                        let boundary = centre + (position - centre).normalize() * radius;
+                        let ctrl = mouth.lerp(boundary, 0.5);
+                        if kind == ElementKind::Sink {
+                                drawing::spawn_flow(&mut commands, &mut ids, system, element, FlowCurve::quadratic(boundary, ctrl, mouth));
+                        } else {
+                                drawing::spawn_flow(&mut commands, &mut ids, element, system, FlowCurve::quadratic(mouth, ctrl, boundary));
+                        }
+                }
+                ElementKind::Disruption => {
+                        if is_occupied(kind, position, &q_elements) {
+                                return;
+                        }
+                        // attach it to the flow, interface or system it's dropped on
+                        let target = selection::hit_test(cursor.0, TARGET_TOLERANCE, &q_shapes)
+                                .filter(|&hit| q_kinds.get(hit).is_ok_and(|kind| {
+                                        matches!(kind, ElementKind::Flow | ElementKind::Interface | ElementKind::System)
+                                }))
+                                .or_else(|| innermost_system(position, &q_elements).map(|(system, ..)| system));
+                        drawing::spawn_disruption(&mut commands, &mut ids, position, target);
+                        edits.send(added);
+                }
+                ElementKind::Interface => {
+                        // interfaces live on the boundary of whichever system is closest
+                        let Some((system, centre, radius)) = nearest_system(position, &q_elements) else {
+                                return;
+                        };
+                        let mut angle = model::angle_around(centre, position);
+                        if let Ok(cp) = q_circum.get(system) {
+                                angle = cp.snap_angle(angle);
+                        }
+                        let on_boundary = centre + Vec2::from_angle(angle) * radius;
+                        if is_occupied(kind, on_boundary, &q_elements) {
+                                return;
+                        }
+                        drawing::spawn_interface(&mut commands, &mut ids, system, angle, (centre, radius));
+                        edits.send(added);
+                }
+                ElementKind::Flow => {
+                        let Some(picked) = pick_element(position, &q_elements) else {
+                                return;
+                        };
+                        match flow_draft.0.take() {
+                                None => flow_draft.0 = Some((picked, position)),
+                                Some((start, from)) if start != picked => {
+                                        // one flow per pair of endpoints is enough
+                                        if q_flows.iter().any(|flow| flow.start == start && flow.end == picked) {
+                                                return;
+                                        }
+                                        let ctrl = from.lerp(position, 0.5);
+                                        drawing::spawn_flow(&mut commands, &mut ids, start, picked, FlowCurve::quadratic(from, ctrl, position));
+                                        edits.send(added);
+                                }
+                                // clicking the start element again cancels the flow
+                                Some(_) => {}
+                        }
+                }
+        }
+}
+
+/// Is there already an element of `kind` at `position`?
+fn is_occupied(kind: ElementKind, position: Vec2, q_elements: &ElementQuery) -> bool {
+        q_elements
+                .iter()
+                .any(|(_, other_kind, transform, _, visibility)| {
+                        *other_kind == kind
+                                && visibility.get()
+                                && transform.translation().xy().distance(position) < DUPLICATE_TOLERANCE
+                })
+}
+
+/// Returns the system whose boundary is closest to `point`, with its centre and radius.
+fn nearest_system(point: Vec2, q_elements: &ElementQuery) -> Option<(Entity, Vec2, f32)> {
+        q_elements
+                .iter()
+                .filter(|(.., visibility)| visibility.get())
+                .filter_map(|(entity, _, transform, system, _)| {
+                        let system = system?;
+                        let centre = transform.translation().xy();
+                        let gap = (centre.distance(point) - system.radius).abs();
+                        Some((gap, (entity, centre, system.radius)))
+                })
+                .min_by(|a, b| a.0.total_cmp(&b.0))
+                .map(|(_, system)| system)
+}
+
+/// Returns the smallest visible system containing `point`, with its centre and radius.
+fn innermost_system(point: Vec2, q_elements: &ElementQuery) -> Option<(Entity, Vec2, f32)> {
+        let visible_systems = q_elements
+                .iter()
+                .filter(|(.., visibility)| visibility.get())
+                .filter_map(|(entity, _, transform, system, _)| Some((entity, transform.translation().xy(), system?.radius)));
+        model::innermost_system(point, visible_systems)
+}
+
+/// Returns the element under `point` that a flow can start or end on, preferring the small
+/// elements drawn on top of systems.
+fn pick_element(point: Vec2, q_elements: &ElementQuery) -> Option<Entity> {
+        let closest = |systems: bool| {
+                q_elements
+                        .iter()
+                        .filter(|(_, kind, ..)| {
+                                matches!(kind, ElementKind::Source | ElementKind::Sink | ElementKind::Interface | ElementKind::System)
+                        })
+                        .filter(|(_, _, _, system, visibility)| system.is_some() == systems && visibility.get())
+                        .filter_map(|(entity, _, transform, system, _)| {
+                                let distance = transform.translation().xy().distance(point);
+                                let reach = system.map_or(PICK_RADIUS, |s| s.radius);
+                                (distance <= reach).then_some((distance, entity))
+                        })
+                        .min_by(|a, b| a.0.total_cmp(&b.0))
+                        .map(|(_, entity)| entity)
+        };
+        closest(false).or_else(|| closest(true))
+}
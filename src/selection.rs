@@ -0,0 +1,559 @@
+use std::collections::HashSet;
+use bevy::prelude::*;
+use bevy_egui::EguiContexts;
+use bevy_prototype_lyon::prelude::*;
+use bevy_prototype_lyon::prelude::tess::path::iterator::PathIterator;
+use bevy_prototype_lyon::prelude::tess::path::PathEvent;
+
+use crate::align::{self, Alignables, Guides};
+use crate::flow;
+use crate::grid::Grid;
+use crate::helper::{self, MainCamera, MyWorldCoords};
+use crate::history::Edit;
+use crate::interface::{self, CircumPoints};
+use crate::model::{self, AutoRoute, ElementKind, Flow, FlowCurve, Interface, ParentSystem, SystemNode};
+use crate::system_node::{self, MIN_SYSTEM_RADIUS};
+use crate::toolbar_menu::ActiveTool;
+use crate::viewport;
+
+/// Tracks which elements are selected and highlights them on the canvas.
+///
+/// With the select tool, clicking an element selects it (Shift-click adds to or removes from
+/// the selection), dragging on empty canvas selects everything inside the box, and dragging
+/// a selected element moves the whole selection. Grabbing a system by its rim resizes it, and
+/// grabbing one of a selected flow's control point handles bends the flow.
+pub struct SelectionPlugin;
+
+impl Plugin for SelectionPlugin {
+        fn build(&self, app: &mut App) {
+                app.init_resource::<Gesture>()
+                        .add_systems(Update, (
+                                select_with_mouse.after(helper::update_cursor_position),
+                                drag_selection,
+                                box_select,
+                        ).chain().before(interface::update_interface))
+                        .add_systems(PostUpdate, highlight_selection);
+        }
+}
+
+/// Marks an element the user has selected.
+#[derive(Component, Debug, Clone, Copy, Default)]
+pub struct Selected;
+
+/// The stroke a selected element had before it was highlighted.
+/// Anything that persists or exports strokes should prefer this over `Stroke`.
+#[derive(Component, Debug, Clone, Copy)]
+pub struct UnselectedStroke(pub Stroke);
+
+const HIGHLIGHT_COLOR: Color = Color::rgb(0.1, 0.45, 0.95);
+/// Extra stroke width given to highlighted elements.
+const HIGHLIGHT_WIDTH: f32 = 2.;
+
+const SELECTION_BOX_COLOR: Color = Color::rgb(0.1, 0.45, 0.95);
+
+// distances on screen, in pixels; they are scaled by the camera's zoom before use
+const HIT_TOLERANCE: f32 = 6.;  // how far off a stroke a click still hits it
+const RIM_GRAB: f32 = 8.;       // how close to a system's boundary a click has to be to resize it
+const DRAG_THRESHOLD: f32 = 4.; // how far the cursor travels before a click becomes a drag
+
+/// Curves are flattened to within this many world units for hit-testing.
+const FLATTEN_TOLERANCE: f32 = 0.5;
+
+/// What the left mouse button is currently doing on the canvas.
+#[derive(Resource, Debug, Clone, Copy, Default)]
+enum Gesture {
+        #[default]
+        Idle,
+        /// Pressed on a selected element; turns into `Moving` once the cursor has travelled far enough.
+        Pressed { origin: Vec2 },
+        /// Dragging the selection, which was last moved to follow the cursor at `last`.
+        Moving { last: Vec2 },
+        /// Dragging a system's boundary.
+        Resizing(Entity),
+        /// Dragging one of a flow's control points, numbered as by `FlowCurve::controls`.
+        Bending { flow: Entity, control: usize },
+        /// Dragging out a selection box from `start`.
+        BoxSelecting { start: Vec2 },
+}
+
+/// Every element that can be clicked on, with its shape.
+pub type HitQuery<'w, 's> = Query<'w, 's, (
+        Entity,
+        &'static Path,
+        Option<&'static Fill>,
+        Option<&'static Stroke>,
+        &'static GlobalTransform,
+        &'static InheritedVisibility,
+), With<ElementKind>>;
+
+/// Returns the topmost visible element whose shape is within `tolerance` of `point`.
+pub fn hit_test(point: Vec2, tolerance: f32, q_shapes: &HitQuery) -> Option<Entity> {
+        q_shapes
+                .iter()
+                .filter(|(.., visibility)| visibility.get())
+                .filter(|(_, path, fill, stroke, transform, _)| {
+                        // elements are never scaled, so distances in their own space are world distances
+                        let local = transform.affine().inverse().transform_point3(point.extend(0.)).xy();
+                        let reach = stroke.map_or(0., |s| s.options.line_width / 2.) + tolerance;
+                        polylines(path).iter().any(|line| {
+                                line.windows(2).any(|segment| distance_to_segment(local, segment[0], segment[1]) <= reach)
+                                        || (fill.is_some() && encloses(line, local))
+                        })
+                })
+                .max_by(|a, b| a.4.translation().z.total_cmp(&b.4.translation().z))
+                .map(|(entity, ..)| entity)
+}
+
+/// The world space box around a shape.
+pub fn world_bounds(path: &Path, transform: &GlobalTransform) -> Option<Rect> {
+        polylines(path)
+                .into_iter()
+                .flatten()
+                .map(|point| transform.transform_point(point.extend(0.)).xy())
+                .fold(None, |bounds: Option<Rect>, point| {
+                        Some(bounds.map_or(Rect::from_corners(point, point), |b| b.union_point(point)))
+                })
+}
+
+/// The path flattened into polylines, one per sub-path. Closed sub-paths end where they started.
+fn polylines(path: &Path) -> Vec<Vec<Vec2>> {
+        let mut lines = Vec::new();
+        let mut line = Vec::new();
+        for event in path.0.iter().flattened(FLATTEN_TOLERANCE) {
+                match event {
+                        PathEvent::Begin { at } => line = vec![Vec2::new(at.x, at.y)],
+                        PathEvent::Line { to, .. } => line.push(Vec2::new(to.x, to.y)),
+                        PathEvent::End { first, close, .. } => {
+                                if close {
+                                        line.push(Vec2::new(first.x, first.y));
+                                }
+                                lines.push(std::mem::take(&mut line));
+                        }
+                        // flattening leaves nothing but lines
+                        PathEvent::Quadratic { .. } | PathEvent::Cubic { .. } => {}
+                }
+        }
+        lines
+}
+
+fn distance_to_segment(point: Vec2, a: Vec2, b: Vec2) -> f32 {
+        let ab = b - a;
+        let t = if ab == Vec2::ZERO { 0. } else { ((point - a).dot(ab) / ab.length_squared()).clamp(0., 1.) };
+        point.distance(a + ab * t)
+}
+
+/// Even-odd test of `point` against the polygon `line`, closing it if it's open (like filling does).
+fn encloses(line: &[Vec2], point: Vec2) -> bool {
+        let mut inside = false;
+        for (i, &a) in line.iter().enumerate() {
+                let b = line[(i + 1) % line.len()];
+                if (a.y > point.y) != (b.y > point.y) && point.x < a.x + (point.y - a.y) / (b.y - a.y) * (b.x - a.x) {
+                        inside = !inside;
+                }
+        }
+        inside
+}
+
+/// Starts a gesture when the left button goes down on the canvas with the select tool.
+#[allow(clippy::too_many_arguments)]
+fn select_with_mouse(
+        mut commands: Commands,
+        mut contexts: EguiContexts,
+        mouse_button_input: Res<ButtonInput<MouseButton>>,
+        keyboard_input: Res<ButtonInput<KeyCode>>,
+        active_tool: Res<State<ActiveTool>>,
+        cursor: Res<MyWorldCoords>,
+        mut gesture: ResMut<Gesture>,
+        q_camera: Query<&OrthographicProjection, With<MainCamera>>,
+        q_shapes: HitQuery,
+        q_selected: Query<Entity, With<Selected>>,
+        q_selected_flows: Query<(Entity, &FlowCurve), With<Selected>>,
+        q_systems: Query<(&SystemNode, &GlobalTransform)>,
+) {
+        if *active_tool.get() != ActiveTool::Select {
+                if !matches!(*gesture, Gesture::Idle) {
+                        *gesture = Gesture::Idle;
+                }
+                return;
+        }
+        // with Space held the left button pans instead
+        if !mouse_button_input.just_pressed(MouseButton::Left)
+                || viewport::space_held(&keyboard_input)
+                || contexts.ctx_mut().is_pointer_over_area()
+        {
+                return;
+        }
+
+        let scale = q_camera.get_single().map_or(1., |projection| projection.scale);
+        let additive = keyboard_input.any_pressed([KeyCode::ShiftLeft, KeyCode::ShiftRight]);
+
+        // handles sit on top of everything else
+        let handle = q_selected_flows.iter().find_map(|(entity, curve)| {
+                curve.controls()
+                        .position(|ctrl| ctrl.distance(cursor.0) <= flow::HANDLE_RADIUS * scale)
+                        .map(|control| (entity, control))
+        });
+        if let Some((flow, control)) = handle.filter(|_| !additive) {
+                *gesture = Gesture::Bending { flow, control };
+                return;
+        }
+
+        let hit = hit_test(cursor.0, HIT_TOLERANCE * scale, &q_shapes);
+        let select_only = |commands: &mut Commands, entity: Entity| {
+                for selected in q_selected.iter().filter(|&selected| selected != entity) {
+                        commands.entity(selected).remove::<Selected>();
+                }
+                commands.entity(entity).insert(Selected);
+        };
+
+        *gesture = match hit {
+                // shift-click toggles, and can still drag what it just added
+                Some(entity) if additive => {
+                        if q_selected.contains(entity) {
+                                commands.entity(entity).remove::<Selected>();
+                                Gesture::Idle
+                        } else {
+                                commands.entity(entity).insert(Selected);
+                                Gesture::Pressed { origin: cursor.0 }
+                        }
+                }
+                Some(entity) => {
+                        let on_rim = q_systems.get(entity).is_ok_and(|(system, transform)| {
+                                (transform.translation().xy().distance(cursor.0) - system.radius).abs() <= RIM_GRAB * scale
+                        });
+                        if on_rim {
+                                select_only(&mut commands, entity);
+                                Gesture::Resizing(entity)
+                        } else {
+                                // pressing on something already selected keeps the selection, so it can all be dragged
+                                if !q_selected.contains(entity) {
+                                        select_only(&mut commands, entity);
+                                }
+                                Gesture::Pressed { origin: cursor.0 }
+                        }
+                }
+                None => {
+                        if !additive {
+                                for selected in &q_selected {
+                                        commands.entity(selected).remove::<Selected>();
+                                }
+                        }
+                        Gesture::BoxSelecting { start: cursor.0 }
+                }
+        };
+}
+
+/// Elements placed by their own transform, rather than along a system (interfaces) or a curve (flows).
+pub type PlacedElements<'w, 's> = Query<'w, 's, &'static mut Transform, (With<ElementKind>, Without<Interface>, Without<Flow>)>;
+
+pub fn centre_of(q_transforms: &PlacedElements, entity: Entity) -> Option<Vec2> {
+        q_transforms.get(entity).ok().map(|transform| transform.translation.xy())
+}
+
+/// What systems take along when they move. Subsystems and interfaces belong to their system,
+/// but sources, sinks and disruptions aren't tied to one, so they go with the innermost system
+/// they sit in. Taken before anything moves.
+pub struct Carry {
+        /// Every system, with its centre and radius.
+        systems: Vec<(Entity, Vec2, f32)>,
+        /// The sources, sinks and disruptions inside a system, with that system.
+        loose: Vec<(Entity, Entity)>,
+}
+
+impl Carry {
+        pub fn new(
+                systems: impl Iterator<Item = (Entity, f32)>,
+                elements: impl Iterator<Item = (Entity, ElementKind)>,
+                q_transforms: &PlacedElements,
+        ) -> Self {
+                let systems: Vec<(Entity, Vec2, f32)> = systems
+                        .filter_map(|(system, radius)| Some((system, centre_of(q_transforms, system)?, radius)))
+                        .collect();
+                let loose = elements
+                        .filter(|(_, kind)| matches!(kind, ElementKind::Source | ElementKind::Sink | ElementKind::Disruption))
+                        .filter_map(|(entity, _)| {
+                                let (system, ..) = model::innermost_system(centre_of(q_transforms, entity)?, systems.iter().copied())?;
+                                Some((entity, system))
+                        })
+                        .collect();
+                Self { systems, loose }
+        }
+
+        /// The sources, sinks and disruptions in the systems `carried` says are moving.
+        pub fn loose_in(&self, carried: impl Fn(Entity) -> bool) -> HashSet<Entity> {
+                self.loose.iter().filter(|(_, system)| carried(*system)).map(|(entity, _)| *entity).collect()
+        }
+
+        /// Moves `system` by `shift`, or as near as it can get while a subsystem stays inside its
+        /// parent, with its subsystems and whatever is in them. Returns whether it moved.
+        pub fn move_system(
+                &self,
+                system: Entity,
+                shift: Vec2,
+                q_parents: &Query<&ParentSystem>,
+                q_transforms: &mut PlacedElements,
+        ) -> bool {
+                let circle = |entity: Entity| self.systems.iter().find(|(other, ..)| *other == entity).map(|(_, centre, radius)| (*centre, *radius));
+                let Some((centre, radius)) = circle(system) else {
+                        return false;
+                };
+                let mut target = centre + shift;
+                if let Some(parent) = q_parents.get(system).ok().and_then(|parent| circle(parent.0)) {
+                        target = system_node::keep_inside(target, radius, parent).0;
+                }
+                let shift = (target - centre).extend(0.);
+                if shift == Vec3::ZERO {
+                        return false;
+                }
+
+                let family: HashSet<Entity> = self.systems
+                        .iter()
+                        .map(|(other, ..)| *other)
+                        .filter(|&other| other == system || model::is_descendant(other, system, q_parents))
+                        .collect();
+                let loose = self.loose.iter().filter(|(_, inside)| family.contains(inside)).map(|(entity, _)| *entity);
+                for member in family.iter().copied().chain(loose) {
+                        if let Ok(mut transform) = q_transforms.get_mut(member) {
+                                transform.translation += shift;
+                        }
+                }
+                true
+        }
+}
+
+/// Moves the selection, or resizes the grabbed system, while the button is held.
+#[allow(clippy::too_many_arguments)]
+fn drag_selection(
+        mut commands: Commands,
+        mouse_button_input: Res<ButtonInput<MouseButton>>,
+        cursor: Res<MyWorldCoords>,
+        grid: Res<Grid>,
+        mut guides: ResMut<Guides>,
+        mut gesture: ResMut<Gesture>,
+        mut edits: EventWriter<Edit>,
+        q_camera: Query<&OrthographicProjection, With<MainCamera>>,
+        q_elements: Query<(Entity, &ElementKind, Has<Selected>)>,
+        q_parents: Query<&ParentSystem>,
+        q_circum: Query<&CircumPoints>,
+        mut q_systems: Query<(Entity, &mut SystemNode)>,
+        mut q_transforms: PlacedElements,
+        mut q_interfaces: Query<&mut Interface>,
+        mut q_flows: Query<(&Flow, &mut FlowCurve)>,
+        q_alignables: Alignables,
+) {
+        let released = !mouse_button_input.pressed(MouseButton::Left);
+        let scale = q_camera.get_single().map_or(1., |projection| projection.scale);
+
+        match *gesture {
+                Gesture::Pressed { origin } => {
+                        if released {
+                                *gesture = Gesture::Idle;
+                        } else if origin.distance(cursor.0) > DRAG_THRESHOLD * scale {
+                                *gesture = Gesture::Moving { last: origin };
+                        }
+                }
+                Gesture::Moving { last } => {
+                        let mut delta = cursor.0 - last;
+                        let selected: Vec<(Entity, ElementKind)> = q_elements
+                                .iter()
+                                .filter(|(.., selected)| *selected)
+                                .map(|(e, k, _)| (e, *k))
+                                .collect();
+                        // when snapping, the element nearest the cursor lands on the grid and the rest keep
+                        // their distance to it; what's left over of the cursor's movement waits for the next frame
+                        if grid.snap {
+                                let lead = selected
+                                        .iter()
+                                        .filter_map(|(entity, _)| centre_of(&q_transforms, *entity))
+                                        .min_by(|a, b| a.distance(last).total_cmp(&b.distance(last)));
+                                if let Some(lead) = lead {
+                                        delta = grid.snap(lead + delta) - lead;
+                                }
+                        }
+                        let moving: HashSet<Entity> = selected.iter().map(|(entity, _)| *entity).collect();
+                        let moving_systems: HashSet<Entity> = selected
+                                .iter()
+                                .filter(|(_, kind)| *kind == ElementKind::System)
+                                .map(|(entity, _)| *entity)
+                                .collect();
+                        // anything on or in a moving system is carried along by it
+                        let carried = |system: Entity| {
+                                moving_systems.contains(&system)
+                                        || model::ancestors(system, &q_parents).iter().any(|a| moving_systems.contains(a))
+                        };
+                        let carry = Carry::new(
+                                q_systems.iter().map(|(system, node)| (system, node.radius)),
+                                q_elements.iter().map(|(entity, kind, _)| (entity, *kind)),
+                                &q_transforms,
+                        );
+                        let carried_along = carry.loose_in(carried);
+
+                        // line what's moving up with what isn't, the same way
+                        let moving_bounds = align::alignable_bounds(&q_alignables, |entity| !moving.contains(&entity))
+                                .into_iter()
+                                .reduce(|a, b| a.union(b));
+                        if let Some(bounds) = moving_bounds {
+                                let others = align::alignable_bounds(&q_alignables, |entity| {
+                                        moving.contains(&entity) || carried(entity) || carried_along.contains(&entity)
+                                });
+                                let moved = Rect::from_corners(bounds.min + delta, bounds.max + delta);
+                                delta += guides.snap(moved, &others, align::GUIDE_TOLERANCE * scale);
+                        }
+
+                        for (entity, kind) in selected {
+                                match kind {
+                                        ElementKind::System => {
+                                                if !model::ancestors(entity, &q_parents).iter().any(|a| moving_systems.contains(a)) {
+                                                        carry.move_system(entity, delta, &q_parents, &mut q_transforms);
+                                                }
+                                        }
+                                        ElementKind::Interface => {
+                                                let Ok(mut interface) = q_interfaces.get_mut(entity) else { continue };
+                                                if carried(interface.system) {
+                                                        continue;
+                                                }
+                                                // interfaces can't leave their system, so they slide round to follow the cursor
+                                                if let Some(centre) = centre_of(&q_transforms, interface.system) {
+                                                        interface.angle = model::angle_around(centre, cursor.0);
+                                                }
+                                        }
+                                        ElementKind::Flow => {
+                                                // flows hang off their ends, so dragging one on its own bends it;
+                                                // when an end is being moved too, the flow follows that instead
+                                                let Ok((flow, mut curve)) = q_flows.get_mut(entity) else { continue };
+                                                if !moving.contains(&flow.start) && !moving.contains(&flow.end) {
+                                                        curve.ctrl += delta;
+                                                        if let Some(ctrl2) = &mut curve.ctrl2 {
+                                                                *ctrl2 += delta;
+                                                        }
+                                                        // bending it by hand takes it off auto-routing
+                                                        commands.entity(entity).remove::<AutoRoute>();
+                                                }
+                                        }
+                                        ElementKind::Source | ElementKind::Sink | ElementKind::Disruption => {
+                                                if carried_along.contains(&entity) {
+                                                        continue;
+                                                }
+                                                if let Ok(mut transform) = q_transforms.get_mut(entity) {
+                                                        transform.translation += delta.extend(0.);
+                                                }
+                                        }
+                                }
+                        }
+
+                        *gesture = Gesture::Moving { last: last + delta };
+                        if released {
+                                // dropped interfaces snap to their system's circum points
+                                for (entity, ..) in q_elements.iter().filter(|(.., selected)| *selected) {
+                                        let Ok(mut interface) = q_interfaces.get_mut(entity) else { continue };
+                                        if let Ok(cp) = q_circum.get(interface.system) {
+                                                interface.angle = cp.snap_angle(interface.angle);
+                                        }
+                                }
+                                edits.send(Edit(match q_elements.iter().filter(|(.., selected)| *selected).count() {
+                                        1 => "Move element".to_string(),
+                                        n => format!("Move {n} elements"),
+                                }));
+                                *gesture = Gesture::Idle;
+                        }
+                }
+                Gesture::Resizing(entity) => {
+                        if let Some(centre) = centre_of(&q_transforms, entity) {
+                                let mut radius = centre.distance(cursor.0).max(MIN_SYSTEM_RADIUS);
+                                if let Ok(parent) = q_parents.get(entity) {
+                                        if let (Some(parent_centre), Ok((_, parent_node))) = (centre_of(&q_transforms, parent.0), q_systems.get(parent.0)) {
+                                                // shrink rather than push the subsystem around while resizing it
+                                                let room = parent_node.radius - centre.distance(parent_centre);
+                                                radius = radius.min(room.max(MIN_SYSTEM_RADIUS));
+                                        }
+                                }
+                                if let Ok((_, mut system)) = q_systems.get_mut(entity) {
+                                        if system.radius != radius {
+                                                system.radius = radius;
+                                        }
+                                }
+                        }
+                        if released {
+                                edits.send(Edit("Resize system".to_string()));
+                                *gesture = Gesture::Idle;
+                        }
+                }
+                Gesture::Bending { flow, control } => {
+                        if let Ok((_, mut curve)) = q_flows.get_mut(flow) {
+                                if curve.controls().nth(control).is_some_and(|ctrl| ctrl != cursor.0) {
+                                        if let Some(ctrl) = curve.control_mut(control) {
+                                                *ctrl = cursor.0;
+                                        }
+                                        commands.entity(flow).remove::<AutoRoute>();
+                                }
+                        }
+                        if released {
+                                edits.send(Edit("Bend flow".to_string()));
+                                *gesture = Gesture::Idle;
+                        }
+                }
+                Gesture::Idle | Gesture::BoxSelecting { .. } => {}
+        }
+}
+
+/// Draws the selection box while it's dragged out, and selects everything inside it once let go.
+fn box_select(
+        mut commands: Commands,
+        mut gizmos: Gizmos,
+        mouse_button_input: Res<ButtonInput<MouseButton>>,
+        cursor: Res<MyWorldCoords>,
+        mut gesture: ResMut<Gesture>,
+        q_shapes: HitQuery,
+) {
+        let Gesture::BoxSelecting { start } = *gesture else {
+                return;
+        };
+        let area = Rect::from_corners(start, cursor.0);
+        if mouse_button_input.pressed(MouseButton::Left) {
+                gizmos.rect_2d(area.center(), 0., area.size(), SELECTION_BOX_COLOR);
+                return;
+        }
+
+        *gesture = Gesture::Idle;
+        if area.is_empty() {
+                return;
+        }
+        for (entity, path, .., transform, visibility) in &q_shapes {
+                let inside = world_bounds(path, transform)
+                        .is_some_and(|bounds| area.contains(bounds.min) && area.contains(bounds.max));
+                if inside && visibility.get() {
+                        commands.entity(entity).insert(Selected);
+                }
+        }
+}
+
+/// How `stroke` looks on a selected element.
+pub fn highlighted(stroke: Stroke) -> Stroke {
+        let mut stroke = stroke;
+        stroke.color = HIGHLIGHT_COLOR;
+        stroke.options.line_width += HIGHLIGHT_WIDTH;
+        stroke
+}
+
+/// Elements selected since the last frame that aren't highlighted yet.
+type NewlySelected = (Added<Selected>, Without<UnselectedStroke>);
+
+pub fn highlight_selection(
+        mut commands: Commands,
+        mut q_selected: Query<(Entity, &mut Stroke), NewlySelected>,
+        mut removed: RemovedComponents<Selected>,
+        mut q_unselected: Query<(&mut Stroke, &UnselectedStroke), Without<Selected>>,
+) {
+        for (entity, mut stroke) in &mut q_selected {
+                commands.entity(entity).insert(UnselectedStroke(*stroke));
+                *stroke = highlighted(*stroke);
+        }
+
+        for entity in removed.read() {
+                let Ok((mut stroke, unselected)) = q_unselected.get_mut(entity) else {
+                        continue;
+                };
+                *stroke = unselected.0;
+                commands.entity(entity).remove::<UnselectedStroke>();
+        }
+}
@@ -0,0 +1,353 @@
+use std::collections::{HashMap, HashSet, VecDeque};
+use bevy::prelude::*;
+use bevy::render::view::RenderLayers;
+
+use crate::drawing;
+use crate::helper;
+use crate::model::{Disruption, DisruptionSchedule, ElementId, ElementKind, Flow, FlowQuantity, Interface, Perturbation, Sink, SystemNode, Throughput};
+
+/// Runs the model over time, on the fixed timestep.
+///
+/// Sources emit what their flows ask for, flows carry it (no faster than the interfaces at
+/// either end allow, which share their capacity among every flow through them, and taking
+/// as long as they take to cross), systems build up a stock of
+/// whatever flows into them and give it out to whatever flows out, and sinks absorb it.
+/// Disruptions cut, throttle or swell the flows of whatever they're attached to while
+/// they're in effect. The toolbar plays, pauses, steps and speeds up the simulation.
+pub struct SimulationPlugin;
+
+impl Plugin for SimulationPlugin {
+        fn build(&self, app: &mut App) {
+                app.init_resource::<Simulation>()
+                        .add_systems(FixedUpdate, step_simulation)
+                        .add_systems(Update, (
+                                reset_simulation,
+                                attach_simulation_state,
+                                label_stocks,
+                        ).chain());
+        }
+}
+
+/// The speeds the simulation can run at, as multiples of real time.
+pub const SPEEDS: [f32; 6] = [0.25, 0.5, 1., 2., 4., 8.];
+
+/// Where the simulation is at, and how it's being run.
+#[derive(Resource, Debug)]
+pub struct Simulation {
+        pub running: bool,
+        /// Simulated seconds per real second.
+        pub speed: f32,
+        /// Simulated seconds since the start.
+        pub time: f32,
+        /// Ticks to take while paused.
+        steps: u32,
+        /// Set to go back to the start before the next tick.
+        restart: bool,
+        /// State of the random number generator, which starts over with the simulation so
+        /// every run goes the same way.
+        seed: u64,
+}
+
+/// Where random disruptions start from.
+const SEED: u64 = 0x2545_F491_4F6C_DD1D;
+
+impl Default for Simulation {
+        fn default() -> Self {
+                Self { running: false, speed: 1., time: 0., steps: 0, restart: false, seed: SEED }
+        }
+}
+
+impl Simulation {
+        /// Takes a single tick, if paused.
+        pub fn step(&mut self) {
+                self.steps += 1;
+        }
+
+        /// Empties every stock and flow, and starts the clock again.
+        pub fn reset(&mut self) {
+                self.restart = true;
+                self.steps = 0;
+        }
+
+        /// A random number in 0..1 (xorshift).
+        fn random(&mut self) -> f32 {
+                self.seed ^= self.seed << 13;
+                self.seed ^= self.seed >> 7;
+                self.seed ^= self.seed << 17;
+                (self.seed >> 40) as f32 / (1u64 << 24) as f32
+        }
+}
+
+/// How much a system has built up, or a sink has absorbed.
+#[derive(Component, Debug, Clone, Copy, Default)]
+pub struct Stock(pub f32);
+
+/// What's going on in a flow.
+#[derive(Component, Debug, Clone, Default)]
+pub struct FlowState {
+        /// How much went into the flow per second, as of the last tick.
+        pub rate: f32,
+        /// Amounts on their way, oldest first.
+        in_transit: VecDeque<Parcel>,
+}
+
+/// What a disruption has done so far.
+#[derive(Component, Debug, Clone, Default)]
+pub struct DisruptionState {
+        /// When the random disruption that's in effect wears off.
+        until: f32,
+        /// When random disruptions struck, and wore off.
+        pub strikes: Vec<(f32, f32)>,
+}
+
+/// Whether `disruption` is in effect at simulated time `now`.
+pub fn in_effect(disruption: &Disruption, state: &DisruptionState, now: f32) -> bool {
+        match disruption.schedule {
+                DisruptionSchedule::At { start, duration } => (start..start + duration).contains(&now),
+                DisruptionSchedule::Random { .. } => now < state.until,
+        }
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Parcel {
+        /// Simulated time it gets to the end of the flow.
+        arrives: f32,
+        amount: f32,
+}
+
+/// What's at one end of a flow, as far as the simulation is concerned.
+enum End {
+        /// A source: it has as much as is asked of it.
+        Endless,
+        /// A system's stock, which it gives from and takes into; interfaces give onto their system's.
+        Stock(Entity),
+        /// A sink, which only ever takes.
+        Sink(Entity),
+        /// Neither gives nor takes anything.
+        Nothing,
+}
+
+fn end_of(entity: Entity, q_kinds: &Query<&ElementKind>, q_interfaces: &Query<(&Interface, &Throughput)>) -> (End, Throughput) {
+        match (q_kinds.get(entity), q_interfaces.get(entity)) {
+                (_, Ok((interface, throughput))) => (End::Stock(interface.system), *throughput),
+                (Ok(ElementKind::Source), _) => (End::Endless, Throughput::default()),
+                (Ok(ElementKind::System), _) => (End::Stock(entity), Throughput::default()),
+                (Ok(ElementKind::Sink), _) => (End::Sink(entity), Throughput::default()),
+                _ => (End::Nothing, Throughput::default()),
+        }
+}
+
+pub fn step_simulation(
+        time: Res<Time>,
+        mut simulation: ResMut<Simulation>,
+        mut q_flows: Query<(Entity, &ElementId, &Flow, &FlowQuantity, &mut FlowState)>,
+        mut q_disruptions: Query<(&Disruption, &mut DisruptionState)>,
+        q_kinds: Query<&ElementKind>,
+        q_interfaces: Query<(&Interface, &Throughput)>,
+        mut q_stocks: Query<&mut Stock>,
+) {
+        if !simulation.running {
+                if simulation.steps == 0 {
+                        return;
+                }
+                simulation.steps -= 1;
+        }
+        let dt = time.delta_seconds() * simulation.speed;
+        simulation.time += dt;
+        let now = simulation.time;
+
+        let mut disrupted = Vec::new();
+        for (disruption, mut state) in &mut q_disruptions {
+                if let DisruptionSchedule::Random { rate, duration } = disruption.schedule {
+                        // strikes come as a Poisson process, and don't pile up
+                        if now >= state.until && simulation.random() < 1. - (-rate.max(0.) * dt).exp() {
+                                state.until = now + duration;
+                                state.strikes.push((now, now + duration));
+                        }
+                }
+                if let Some(target) = disruption.target.filter(|_| in_effect(disruption, &state, now)) {
+                        disrupted.push((target, disruption.perturbation));
+                }
+        }
+
+        // what the interfaces have left to let through this tick; flows take from it in the
+        // order they were made, so the same flows get through every run
+        let mut capacity_left: HashMap<Entity, f32> = HashMap::new();
+        let mut flows: Vec<_> = q_flows.iter().map(|(entity, id, ..)| (*id, entity)).collect();
+        flows.sort_unstable();
+
+        for (_, entity) in flows {
+                let Ok((_, _, flow, quantity, mut state)) = q_flows.get_mut(entity) else {
+                        continue;
+                };
+                let (start, start_throughput) = end_of(flow.start, &q_kinds, &q_interfaces);
+                let (end, end_throughput) = end_of(flow.end, &q_kinds, &q_interfaces);
+
+                // disruptions on the flow, either end of it, or the system at either end
+                let mut asked = 1.;
+                let mut let_through = 1.;
+                for &(target, perturbation) in &disrupted {
+                        let on_flow = target == entity || target == flow.start || target == flow.end;
+                        let into = on_flow || matches!(end, End::Stock(holder) | End::Sink(holder) if holder == target);
+                        let out_of = matches!(start, End::Stock(holder) if holder == target);
+                        match perturbation {
+                                Perturbation::CutFlow if into || out_of => let_through = 0.,
+                                Perturbation::ReduceCapacity { to } if into || out_of => let_through *= to.clamp(0., 1.),
+                                Perturbation::SpikeInput { by } if into => asked *= by.max(0.),
+                                _ => {}
+                        }
+                }
+
+                // take what the flow asks for, as far as the interfaces and the stock at the start allow
+                let mut amount = quantity.rate.max(0.) * asked * dt;
+                let interfaces = [(flow.start, start_throughput), (flow.end, end_throughput)];
+                for (interface, throughput) in interfaces {
+                        if let Some(capacity) = throughput.capacity {
+                                let left = capacity_left.entry(interface).or_insert(capacity.max(0.) * dt);
+                                amount = amount.min(*left);
+                        }
+                }
+                amount *= let_through;
+                match start {
+                        End::Endless => {}
+                        End::Stock(holder) => match q_stocks.get_mut(holder) {
+                                Ok(mut stock) => {
+                                        amount = amount.min(stock.0);
+                                        stock.0 -= amount;
+                                }
+                                Err(_) => amount = 0.,
+                        },
+                        End::Sink(_) | End::Nothing => amount = 0.,
+                }
+                for (interface, _) in interfaces {
+                        if let Some(left) = capacity_left.get_mut(&interface) {
+                                *left -= amount;
+                        }
+                }
+                state.rate = if dt > 0. { amount / dt } else { 0. };
+                if amount > 0. {
+                        let arrives = now + start_throughput.delay + end_throughput.delay;
+                        state.in_transit.push_back(Parcel { arrives, amount });
+                }
+
+                // hand over whatever has made it across
+                let mut delivered = 0.;
+                while state.in_transit.front().is_some_and(|parcel| parcel.arrives <= now) {
+                        delivered += state.in_transit.pop_front().map_or(0., |parcel| parcel.amount);
+                }
+                if let End::Stock(holder) | End::Sink(holder) = end {
+                        if let Ok(mut stock) = q_stocks.get_mut(holder) {
+                                stock.0 += delivered;
+                        }
+                }
+        }
+}
+
+/// Anything that holds a stock but hasn't been given one yet.
+type WithoutStock = (Or<(With<SystemNode>, With<Sink>)>, Without<Stock>);
+
+/// Gives new systems and sinks an empty stock, new flows nothing in transit, and new
+/// disruptions a clean record.
+fn attach_simulation_state(
+        mut commands: Commands,
+        q_holders: Query<Entity, WithoutStock>,
+        q_flows: Query<Entity, (With<Flow>, Without<FlowState>)>,
+        q_disruptions: Query<Entity, (With<Disruption>, Without<DisruptionState>)>,
+) {
+        for entity in &q_holders {
+                commands.entity(entity).insert(Stock::default());
+        }
+        for entity in &q_flows {
+                commands.entity(entity).insert(FlowState::default());
+        }
+        for entity in &q_disruptions {
+                commands.entity(entity).insert(DisruptionState::default());
+        }
+}
+
+fn reset_simulation(
+        mut simulation: ResMut<Simulation>,
+        mut q_stocks: Query<&mut Stock>,
+        mut q_flows: Query<&mut FlowState>,
+        mut q_disruptions: Query<&mut DisruptionState>,
+) {
+        if !simulation.restart {
+                return;
+        }
+        simulation.restart = false;
+        simulation.time = 0.;
+        simulation.seed = SEED;
+        for mut state in &mut q_disruptions {
+                *state = DisruptionState::default();
+        }
+        for mut stock in &mut q_stocks {
+                *stock = Stock::default();
+        }
+        for mut state in &mut q_flows {
+                *state = FlowState::default();
+        }
+}
+
+/// Shows a stock's value under whatever holds it.
+#[derive(Component, Debug, Clone, Copy)]
+struct StockLabel(Entity);
+
+const LABEL_SIZE: f32 = 20.;
+/// Space between a label and what it's under.
+const LABEL_GAP: f32 = 20.;
+
+type StockLabels<'w, 's> = Query<'w, 's, (
+        Entity,
+        &'static StockLabel,
+        &'static mut Text,
+        &'static mut Transform,
+        &'static mut Visibility,
+), Without<Stock>>;
+
+/// Labels every stock with its value once the simulation has started.
+fn label_stocks(
+        mut commands: Commands,
+        simulation: Res<Simulation>,
+        q_stocks: Query<(Entity, &Stock, &Transform, Option<&SystemNode>, &InheritedVisibility)>,
+        mut q_labels: StockLabels,
+) {
+        let mut labelled = HashSet::new();
+        for (label_entity, label, mut text, mut transform, mut visibility) in &mut q_labels {
+                let Ok((_, stock, owner, system, owner_visibility)) = q_stocks.get(label.0) else {
+                        commands.entity(label_entity).despawn();
+                        continue;
+                };
+                labelled.insert(label.0);
+
+                let value = format!("{:.1}", stock.0);
+                if text.sections[0].value != value {
+                        text.sections[0].value = value;
+                }
+                // below a system's boundary, or a sink's basin
+                let below = system.map_or(drawing::BASIN_WALL.max(drawing::BASIN_WIDTH / 2.), |system| system.radius) + LABEL_GAP;
+                let position = (owner.translation.xy() - Vec2::Y * below).extend(drawing::LABEL_DEPTH);
+                if transform.translation != position {
+                        transform.translation = position;
+                }
+                let shown = if simulation.time > 0. && owner_visibility.get() { Visibility::Inherited } else { Visibility::Hidden };
+                if *visibility != shown {
+                        *visibility = shown;
+                }
+        }
+
+        for (entity, ..) in q_stocks.iter().filter(|(entity, ..)| !labelled.contains(entity)) {
+                commands.spawn((
+                        Text2dBundle {
+                                text: Text::from_section("", TextStyle {
+                                        font_size: LABEL_SIZE,
+                                        color: Color::BLACK,
+                                        ..default()
+                                }),
+                                visibility: Visibility::Hidden,
+                                ..default()
+                        },
+                        StockLabel(entity),
+                        RenderLayers::layer(helper::MAIN_VIEW_LAYER),
+                ));
+        }
+}
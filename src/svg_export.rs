@@ -0,0 +1,240 @@
+use std::fmt::Write;
+use std::path::Path as FilePath;
+
+use bevy::prelude::*;
+use bevy_egui::EguiContexts;
+use bevy_prototype_lyon::prelude::*;
+use bevy_prototype_lyon::prelude::tess::path::PathEvent;
+
+use crate::document::DocumentPath;
+use crate::model::ElementKind;
+use crate::selection::UnselectedStroke;
+
+/// Writes the canvas to an SVG file next to the document with Ctrl+E.
+pub struct SvgExportPlugin;
+
+impl Plugin for SvgExportPlugin {
+        fn build(&self, app: &mut App) {
+                app.add_systems(Update, export_shortcut);
+        }
+}
+
+/// Room left around the drawing, in world units.
+const MARGIN: f32 = 20.;
+
+/// The lyon shape of every element on the canvas; circum-point dots and particles aren't part of the diagram.
+pub type ShapeQuery<'w, 's> = Query<'w, 's, (
+        &'static Path,
+        Option<&'static Fill>,
+        Option<&'static Stroke>,
+        Option<&'static UnselectedStroke>,
+        &'static GlobalTransform,
+        &'static InheritedVisibility,
+), With<ElementKind>>;
+
+/// One lyon shape as it is drawn on the canvas.
+pub struct SvgShape<'a> {
+        pub path: &'a Path,
+        pub fill: Option<&'a Fill>,
+        pub stroke: Option<&'a Stroke>,
+        pub transform: &'a GlobalTransform,
+}
+
+/// Renders `shapes` to a standalone SVG document.
+/// Shapes are painted in z order, like the canvas does, and the view box is fitted to them.
+pub fn render_svg(mut shapes: Vec<SvgShape<'_>>, background: Option<Color>) -> String {
+        shapes.sort_by(|a, b| a.transform.translation().z.total_cmp(&b.transform.translation().z));
+
+        let mut body = String::new();
+        let mut min = Vec2::splat(f32::INFINITY);
+        let mut max = Vec2::splat(f32::NEG_INFINITY);
+        for shape in &shapes {
+                let half_stroke = shape.stroke.map_or(0., |s| s.options.line_width / 2.);
+                // world space is y-up, SVG is y-down
+                let to_svg = |p: tess::math::Point| {
+                        let world = shape.transform.transform_point(Vec3::new(p.x, p.y, 0.)).xy();
+                        min = min.min(world - half_stroke);
+                        max = max.max(world + half_stroke);
+                        Vec2::new(world.x, -world.y)
+                };
+                let data = path_data(shape.path, to_svg);
+                if data.is_empty() {
+                        continue;
+                }
+
+                let mut attributes = String::new();
+                match shape.fill {
+                        Some(fill) => {
+                                let rule = match fill.options.fill_rule {
+                                        FillRule::EvenOdd => "evenodd",
+                                        FillRule::NonZero => "nonzero",
+                                };
+                                let _ = write!(attributes, r#" fill="{}" fill-rule="{rule}""#, colour(fill.color));
+                        }
+                        None => attributes.push_str(r#" fill="none""#),
+                }
+                if let Some(stroke) = shape.stroke {
+                        let _ = write!(
+                                attributes,
+                                r#" stroke="{}" stroke-width="{}" stroke-linejoin="{}" stroke-linecap="{}""#,
+                                colour(stroke.color),
+                                stroke.options.line_width,
+                                line_join(stroke.options.line_join),
+                                line_cap(stroke.options.start_cap),
+                        );
+                }
+                let _ = writeln!(body, r#"  <path d="{data}"{attributes}/>"#);
+        }
+
+        if shapes.is_empty() || min.x > max.x {
+                min = Vec2::ZERO;
+                max = Vec2::ZERO;
+        }
+        let (min, max) = (min - MARGIN, max + MARGIN);
+        let size = max - min;
+        // the view box is in SVG (y-down) coordinates
+        let (x, y) = (min.x, -max.y);
+
+        let mut svg = String::new();
+        let _ = writeln!(
+                svg,
+                r#"<svg xmlns="http://www.w3.org/2000/svg" viewBox="{x} {y} {w} {h}" width="{w}" height="{h}">"#,
+                w = size.x,
+                h = size.y,
+        );
+        if let Some(background) = background {
+                let _ = writeln!(
+                        svg,
+                        r#"  <rect x="{x}" y="{y}" width="{}" height="{}" fill="{}"/>"#,
+                        size.x, size.y, colour(background),
+                );
+        }
+        svg.push_str(&body);
+        svg.push_str("</svg>\n");
+        svg
+}
+
+/// The `d` attribute for a lyon path, with every point mapped through `to_svg`.
+fn path_data(path: &Path, mut to_svg: impl FnMut(tess::math::Point) -> Vec2) -> String {
+        let mut data = String::new();
+        for event in path.0.iter() {
+                let _ = match event {
+                        PathEvent::Begin { at } => {
+                                let at = to_svg(at);
+                                write!(data, "M{} {} ", at.x, at.y)
+                        }
+                        PathEvent::Line { to, .. } => {
+                                let to = to_svg(to);
+                                write!(data, "L{} {} ", to.x, to.y)
+                        }
+                        PathEvent::Quadratic { ctrl, to, .. } => {
+                                let (ctrl, to) = (to_svg(ctrl), to_svg(to));
+                                write!(data, "Q{} {} {} {} ", ctrl.x, ctrl.y, to.x, to.y)
+                        }
+                        PathEvent::Cubic { ctrl1, ctrl2, to, .. } => {
+                                let (ctrl1, ctrl2, to) = (to_svg(ctrl1), to_svg(ctrl2), to_svg(to));
+                                write!(data, "C{} {} {} {} {} {} ", ctrl1.x, ctrl1.y, ctrl2.x, ctrl2.y, to.x, to.y)
+                        }
+                        PathEvent::End { close: true, .. } => write!(data, "Z "),
+                        PathEvent::End { close: false, .. } => Ok(()),
+                };
+        }
+        data.trim_end().to_string()
+}
+
+fn colour(color: Color) -> String {
+        let [r, g, b, a] = color.as_rgba_u8();
+        if a == u8::MAX {
+                format!("#{r:02x}{g:02x}{b:02x}")
+        } else {
+                format!("rgba({r},{g},{b},{:.3})", color.a())
+        }
+}
+
+fn line_join(join: LineJoin) -> &'static str {
+        match join {
+                LineJoin::Round => "round",
+                LineJoin::Bevel => "bevel",
+                LineJoin::Miter | LineJoin::MiterClip => "miter",
+        }
+}
+
+fn line_cap(cap: LineCap) -> &'static str {
+        match cap {
+                LineCap::Butt   => "butt",
+                LineCap::Round  => "round",
+                LineCap::Square => "square",
+        }
+}
+
+/// Writes every visible element on the canvas to `path`, without selection highlights.
+pub fn export_svg(
+        path: &FilePath,
+        q_shapes: &ShapeQuery,
+        background: Color,
+) -> std::io::Result<()> {
+        let shapes = q_shapes
+                .iter()
+                .filter(|(.., visibility)| visibility.get())
+                .map(|(path, fill, stroke, unselected, transform, _)| SvgShape {
+                        path,
+                        fill,
+                        stroke: unselected.map(|u| &u.0).or(stroke),
+                        transform,
+                })
+                .collect();
+        std::fs::write(path, render_svg(shapes, Some(background)))
+}
+
+fn export_shortcut(
+        mut contexts: EguiContexts,
+        keyboard_input: Res<ButtonInput<KeyCode>>,
+        document_path: Res<DocumentPath>,
+        clear_color: Res<ClearColor>,
+        q_shapes: ShapeQuery,
+) {
+        let ctrl = keyboard_input.any_pressed([KeyCode::ControlLeft, KeyCode::ControlRight]);
+        if !(ctrl && keyboard_input.just_pressed(KeyCode::KeyE)) || contexts.ctx_mut().wants_keyboard_input() {
+                return;
+        }
+
+        let path = document_path.0.with_extension("svg");
+        match export_svg(&path, &q_shapes, clear_color.0) {
+                Ok(())  => info!("exported canvas to {}", path.display()),
+                Err(e) => error!("could not export {}: {e}", path.display()),
+        }
+}
+
+#[cfg(test)]
+mod tests {
+        use super::*;
+
+        fn line(from: Vec2, to: Vec2) -> Path {
+                let mut path_builder = PathBuilder::new();
+                path_builder.move_to(from);
+                path_builder.line_to(to);
+                path_builder.build()
+        }
+
+        #[test]
+        fn shapes_in_z_order_with_y_flipped() {
+                let (top, bottom) = (line(Vec2::ZERO, Vec2::new(5., 0.)), line(Vec2::ZERO, Vec2::new(0., 5.)));
+                let fill = Fill::color(Color::RED);
+                let stroke = Stroke::new(Color::rgba(0., 0., 1., 0.5), 2.);
+                let above = GlobalTransform::from_translation(Vec3::new(10., 20., 2.));
+                let below = GlobalTransform::from_translation(Vec3::new(0., 0., 1.));
+                let svg = render_svg(
+                        vec![
+                                SvgShape { path: &top, fill: Some(&fill), stroke: None, transform: &above },
+                                SvgShape { path: &bottom, fill: None, stroke: Some(&stroke), transform: &below },
+                        ],
+                        None,
+                );
+
+                let paths: Vec<_> = svg.lines().filter(|line| line.contains("<path")).collect();
+                assert_eq!(paths, [
+                        r##"  <path d="M0 -0 L0 -5" fill="none" stroke="rgba(0,0,255,0.500)" stroke-width="2" stroke-linejoin="miter" stroke-linecap="butt"/>"##,
+                        r##"  <path d="M10 -20 L15 -20" fill="#ff0000" fill-rule="evenodd"/>"##,
+                ]);
+        }
+}
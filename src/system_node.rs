@@ -0,0 +1,39 @@
+use bevy::prelude::*;
+use bevy_prototype_lyon::prelude::*;
+
+use crate::drawing;
+use crate::model::SystemNode;
+
+/// Keeps each system's circle in step with its radius.
+/// Moving and resizing them with the mouse is up to the selection.
+pub struct SystemNodePlugin;
+
+impl Plugin for SystemNodePlugin {
+        fn build(&self, app: &mut App) {
+                app.add_systems(Update, update_system_shape);
+        }
+}
+
+/// Systems can't be shrunk below this radius.
+pub const MIN_SYSTEM_RADIUS: f32 = 20.;
+
+/// Keeps a subsystem at `centre` with `radius` inside its parent's circle,
+/// returning the corrected centre and radius.
+pub fn keep_inside(centre: Vec2, radius: f32, (parent_centre, parent_radius): (Vec2, f32)) -> (Vec2, f32) {
+        let radius = radius.min(parent_radius);
+        let reach = parent_radius - radius;
+        if centre.distance(parent_centre) > reach {
+                (parent_centre + (centre - parent_centre).normalize_or_zero() * reach, radius)
+        } else {
+                (centre, radius)
+        }
+}
+
+/// Redraws a system's circle after it has been resized.
+fn update_system_shape(
+        mut q_systems: Query<(&SystemNode, &mut Path), Changed<SystemNode>>,
+) {
+        for (system, mut path) in &mut q_systems {
+                *path = drawing::system_path(system.radius);
+        }
+}
@@ -1,8 +1,14 @@
 use bevy::prelude::*;
 use bevy_egui::{EguiContexts, EguiPlugin};
-// helper crate to use e-gui to tweak UI as we build it. Can be removed at the end.
+use bevy::input::common_conditions::input_toggle_active;
 use bevy_inspector_egui::quick::WorldInspectorPlugin;
 
+use crate::flow::FlowStyling;
+use crate::grid::Grid;
+use crate::model::ElementKind;
+use crate::simulation::{self, Simulation};
+use crate::viewport::FitAll;
+
 pub struct ToolbarMenuPlugin;
 
 impl Plugin for ToolbarMenuPlugin {
@@ -12,44 +18,139 @@ impl Plugin for ToolbarMenuPlugin {
         ) {
                 app
                         .add_plugins(EguiPlugin)                    // Adds all Egui resources and render graph nodes.
-                        .add_plugins(WorldInspectorPlugin::new())   // adds inspector plugin to tweak UI as we build it. Can be removed at the end.
+                        // raw ECS view for debugging, behind F12; the inspector panel is what users edit elements with
+                        .add_plugins(WorldInspectorPlugin::new().run_if(input_toggle_active(false, KeyCode::F12)))
+                        .init_state::<ActiveTool>()
 
                         .add_systems(Update, setup_toolbar_menu);
         }
 }
 
+/// The tool a left-click on the canvas uses, picked from the toolbar.
+#[derive(States, Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub enum ActiveTool {
+        /// Clicks don't place anything.
+        #[default]
+        Select,
+        /// Clicks place a new element of this kind.
+        Place(ElementKind),
+}
+
+impl ActiveTool {
+        pub fn label(&self) -> &'static str {
+                match self {
+                        ActiveTool::Select      => "Select",
+                        ActiveTool::Place(kind) => kind.label(),
+                }
+        }
+}
+
+/// A square toolbar button, outlined more heavily while `selected`.
+fn toolbar_button(label: &str, selected: bool) -> egui::Button<'static> {
+        let text = egui::widget_text::WidgetText::RichText(
+                egui::RichText::new(label).color(egui::Color32::BLACK)
+        );
+        egui::Button::new(text)
+                .selected(selected)
+                .stroke(egui::Stroke::new(if selected { 2. } else { 1. }, egui::Color32::BLACK))
+                .rounding(10.)
+}
+
+/// The white, rounded frame shared by the toolbar and the other panels floating over the canvas.
+pub fn floating_frame() -> egui::Frame {
+        egui::Frame::default()
+                .fill(egui::Color32::WHITE)
+                .stroke(egui::Stroke::new(1., egui::Color32::from_rgba_premultiplied(0, 0, 0, 100)))
+                .rounding(10.)
+                .shadow(egui::epaint::Shadow::small_light())
+                .inner_margin(10.)
+                .outer_margin(15.)
+}
+
 fn setup_toolbar_menu(
-        mut contexts: EguiContexts               // EguiContexts is a Bevy Resource that holds the EguiContext
+        mut contexts: EguiContexts,              // EguiContexts is a Bevy Resource that holds the EguiContext
+        active_tool: Res<State<ActiveTool>>,
+        mut next_tool: ResMut<NextState<ActiveTool>>,
+        mut flow_styling: ResMut<FlowStyling>,
+        mut fit_all: EventWriter<FitAll>,
+        mut grid: ResMut<Grid>,
+        mut sim: ResMut<Simulation>,
 ) {
-        let valid_menu_options = vec!["System", "Flow", "Interface", "Source", "Sink", "Disruption"];
+        let valid_menu_options = std::iter::once(ActiveTool::Select)
+                .chain(ElementKind::ALL.map(ActiveTool::Place));
         
         egui::Window::new("Toolbar Menu")
                 .title_bar(false)
                 .movable(false)
                 .resizable(false)
                 .anchor(egui::Align2::CENTER_BOTTOM, egui::Vec2::ZERO)
-                .frame(
-                        egui::Frame::default()
-                                .fill(egui::Color32::WHITE)
-                                .stroke(egui::Stroke::new(1., egui::Color32::from_rgba_premultiplied(0, 0, 0, 100)))
-                                .rounding(10.)
-                                .shadow(egui::epaint::Shadow::small_light())
-                                .inner_margin(10.)
-                                .outer_margin(15.)
-                )
+                .frame(floating_frame())
                 .show(contexts.ctx_mut(), |ui| {
-                        ui.allocate_ui_with_layout(egui::vec2(600.0, 50.0), egui::Layout::left_to_right(egui::Align::Center), |option_container| {
+                        ui.allocate_ui_with_layout(egui::vec2(1200.0, 50.0), egui::Layout::left_to_right(egui::Align::Center), |option_container| {
+                                // no explicit fill on the buttons, so egui picks it from these per interaction state
+                                let visuals = option_container.visuals_mut();
+                                visuals.widgets.inactive.weak_bg_fill = egui::Color32::LIGHT_GRAY;
+                                visuals.widgets.hovered.weak_bg_fill  = egui::Color32::from_rgb(200, 220, 240);
+                                visuals.widgets.active.weak_bg_fill   = egui::Color32::from_rgb(160, 190, 220);
+                                visuals.selection.bg_fill             = egui::Color32::from_rgb(120, 170, 220);
+
                                 for menu_option in valid_menu_options {
-                                        let menu_option_text = egui::widget_text::WidgetText::RichText(
-                                                egui::RichText::new(menu_option.to_string())
-                                                        .color(egui::Color32::BLACK)                      
-                                        );
-                                        let button = egui::Button::new(menu_option_text)
-                                                .fill(egui::Color32::LIGHT_GRAY)
-                                                .stroke(egui::Stroke::new(1., egui::Color32::BLACK))
-                                                .rounding(10.);
-                                        option_container.add_sized([50., 50.], button);            
+                                        let selected = *active_tool.get() == menu_option;
+                                        let button = toolbar_button(menu_option.label(), selected);
+                                        if option_container.add_sized([50., 50.], button).clicked() {
+                                                // clicking the active tool again drops back to plain selection
+                                                next_tool.set(if selected { ActiveTool::Select } else { menu_option });
+                                        }
+                                }
+
+                                option_container.separator();
+                                if option_container.add_sized([50., 50.], toolbar_button("Sankey", flow_styling.sankey))
+                                        .on_hover_text("Draw flows as wide as their rates")
+                                        .clicked()
+                                {
+                                        flow_styling.sankey = !flow_styling.sankey;
+                                }
+                                if option_container.add_sized([50., 50.], toolbar_button("Fit", false))
+                                        .on_hover_text("Frame everything (Home)")
+                                        .clicked()
+                                {
+                                        fit_all.send(FitAll);
+                                }
+                                if option_container.add_sized([50., 50.], toolbar_button("Grid", grid.visible)).clicked() {
+                                        grid.visible = !grid.visible;
+                                }
+                                if option_container.add_sized([50., 50.], toolbar_button("Rulers", grid.rulers)).clicked() {
+                                        grid.rulers = !grid.rulers;
+                                }
+                                if option_container.add_sized([50., 50.], toolbar_button("Snap", grid.snap))
+                                        .on_hover_text("Snap placed and moved elements to the grid")
+                                        .clicked()
+                                {
+                                        grid.snap = !grid.snap;
+                                }
+
+                                // simulation
+                                option_container.separator();
+                                if option_container.add_sized([50., 50.], toolbar_button(if sim.running { "Pause" } else { "Play" }, sim.running)).clicked() {
+                                        sim.running = !sim.running;
+                                }
+                                if option_container.add_enabled(!sim.running, toolbar_button("Step", false).min_size(egui::vec2(50., 50.))).clicked() {
+                                        sim.step();
+                                }
+                                if option_container.add_sized([50., 50.], toolbar_button("Reset", false)).clicked() {
+                                        sim.reset();
                                 }
+                                egui::ComboBox::from_id_source("simulation speed")
+                                        .width(60.)
+                                        .selected_text(format!("{}×", sim.speed))
+                                        .show_ui(option_container, |ui| {
+                                                for speed in simulation::SPEEDS {
+                                                        ui.selectable_value(&mut sim.speed, speed, format!("{speed}×"));
+                                                }
+                                        });
+                                option_container.label(
+                                        egui::RichText::new(format!("t = {:.1} s", sim.time)).color(egui::Color32::BLACK).monospace()
+                                );
                         });
                 });
         
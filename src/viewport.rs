@@ -0,0 +1,272 @@
+use bevy::input::mouse::{MouseScrollUnit, MouseWheel};
+use bevy::input::touchpad::TouchpadMagnify;
+use bevy::prelude::*;
+use bevy::window::PrimaryWindow;
+use bevy_egui::EguiContexts;
+
+use crate::helper::{self, MainCamera, MyWorldCoords};
+use crate::selection::{self, HitQuery};
+
+/// Moves the main camera around the canvas.
+///
+/// The scroll wheel and trackpad pinches zoom in and out around the cursor, and Minus/Equal
+/// around the middle of the window; zooming eases in rather than jumping. Dragging with the
+/// middle button, or the left one while Space is held, pans. Home (or Shift+1, or the
+/// toolbar's Fit button) frames everything on the canvas.
+pub struct ViewportPlugin;
+
+impl Plugin for ViewportPlugin {
+        fn build(&self, app: &mut App) {
+                app.init_resource::<CameraTarget>()
+                        .add_event::<FitAll>()
+                        .add_systems(Update, (
+                                (zoom_with_wheel, zoom_with_keys).after(helper::update_cursor_position),
+                                pan_camera,
+                                fit_all,
+                                ease_camera,
+                        ).chain());
+        }
+}
+
+// how far the camera can zoom out and in
+const MIN_SCALE: f32 = 0.05;
+const MAX_SCALE: f32 = 20.;
+
+/// How much a notch of the wheel zooms (as the log of the factor)...
+const LINE_ZOOM: f32 = 0.15;
+/// ...and a pixel of smooth scrolling.
+const PIXEL_ZOOM: f32 = 0.005;
+/// How much Minus and Equal zoom with each press.
+const KEY_ZOOM: f32 = 1.25;
+
+/// How quickly the camera catches up with where it's headed; higher is snappier.
+const EASE_RATE: f32 = 14.;
+/// Close enough to call it there: as a fraction of the scale, and in screen pixels.
+const SETTLED_SCALE: f32 = 0.001;
+const SETTLED_DISTANCE: f32 = 0.5;
+
+/// Room left around everything when fitting it in the window, as a fraction of its size.
+const FIT_MARGIN: f32 = 0.1;
+
+/// Asks for everything on the canvas to be framed.
+#[derive(Event, Debug, Clone, Copy, Default)]
+pub struct FitAll;
+
+/// Where the main camera is headed. It eases towards `scale`, keeping `anchor` (a point in
+/// the world) still on screen as it does; or, when framing something, towards `centre` too.
+#[derive(Resource, Debug)]
+pub struct CameraTarget {
+        scale: f32,
+        anchor: Option<Vec2>,
+        centre: Option<Vec2>,
+}
+
+impl Default for CameraTarget {
+        fn default() -> Self {
+                Self { scale: 1., anchor: None, centre: None }
+        }
+}
+
+impl CameraTarget {
+        /// Zooms by `factor` (above 1 zooms out) around `anchor`, or the middle of the window.
+        pub fn zoom(&mut self, factor: f32, anchor: Option<Vec2>) {
+                self.scale = (self.scale * factor).clamp(MIN_SCALE, MAX_SCALE);
+                self.anchor = anchor;
+                self.centre = None;
+        }
+
+        /// Glides over to `centre`, at `scale`.
+        pub fn frame(&mut self, centre: Vec2, scale: f32) {
+                self.scale = scale.clamp(MIN_SCALE, MAX_SCALE);
+                self.anchor = None;
+                self.centre = Some(centre);
+        }
+
+        /// Glides over to `centre`, staying at the same zoom.
+        pub fn look_at(&mut self, centre: Vec2) {
+                self.frame(centre, self.scale);
+        }
+
+        /// Glides over to show `area` as large as fits in `window`, with a margin.
+        pub fn frame_area(&mut self, area: Rect, window: &Window) {
+                let size = area.size() * (1. + FIT_MARGIN);
+                let scale = (size.x / window.width().max(1.)).max(size.y / window.height().max(1.));
+                self.frame(area.center(), scale);
+        }
+
+        /// Stops gliding anywhere, e.g. when the camera has been dragged; zooming carries on
+        /// around the middle of the window.
+        pub fn hold(&mut self) {
+                self.anchor = None;
+                self.centre = None;
+        }
+}
+
+fn zoom_with_wheel(
+        mut contexts: EguiContexts,
+        mut wheel: EventReader<MouseWheel>,
+        mut magnify: EventReader<TouchpadMagnify>,
+        cursor: Res<MyWorldCoords>,
+        mut target: ResMut<CameraTarget>,
+) {
+        // positive zooms in
+        let mut zoom = 0.;
+        for event in wheel.read() {
+                zoom += match event.unit {
+                        MouseScrollUnit::Line  => event.y * LINE_ZOOM,
+                        MouseScrollUnit::Pixel => event.y * PIXEL_ZOOM,
+                };
+        }
+        for event in magnify.read() {
+                zoom += event.0;
+        }
+        // scrolling over a panel scrolls the panel
+        if zoom == 0. || contexts.ctx_mut().is_pointer_over_area() {
+                return;
+        }
+        target.zoom((-zoom).exp(), Some(cursor.0));
+}
+
+fn zoom_with_keys(
+        mut contexts: EguiContexts,
+        keyboard_input: Res<ButtonInput<KeyCode>>,
+        mut target: ResMut<CameraTarget>,
+) {
+        if contexts.ctx_mut().wants_keyboard_input() {
+                return;
+        }
+        if keyboard_input.just_pressed(KeyCode::Minus) {
+                target.zoom(KEY_ZOOM, None);
+        }
+        if keyboard_input.just_pressed(KeyCode::Equal) {
+                target.zoom(1. / KEY_ZOOM, None);
+        }
+}
+
+/// Whether the camera is being dragged around, i.e. the user pressed the middle button (or the
+/// left one, with Space held) on the canvas and hasn't let go yet.
+#[derive(Default)]
+struct Panning {
+        button: Option<MouseButton>,
+        /// Where the cursor was on screen last frame.
+        last: Vec2,
+}
+
+/// Whether Space is held down to pan with the left button, so other tools can leave it alone.
+pub fn space_held(keyboard_input: &ButtonInput<KeyCode>) -> bool {
+        keyboard_input.pressed(KeyCode::Space)
+}
+
+fn pan_camera(
+        mut contexts: EguiContexts,
+        mut panning: Local<Panning>,
+        mouse_button_input: Res<ButtonInput<MouseButton>>,
+        keyboard_input: Res<ButtonInput<KeyCode>>,
+        mut target: ResMut<CameraTarget>,
+        q_window: Query<&Window, With<PrimaryWindow>>,
+        mut q_camera: Query<(&mut Transform, &OrthographicProjection), With<MainCamera>>,
+) {
+        let Some(cursor) = q_window.get_single().ok().and_then(|window| window.cursor_position()) else {
+                return;
+        };
+        if panning.button.is_none() && !contexts.ctx_mut().is_pointer_over_area() {
+                if mouse_button_input.just_pressed(MouseButton::Middle) {
+                        panning.button = Some(MouseButton::Middle);
+                } else if mouse_button_input.just_pressed(MouseButton::Left) && space_held(&keyboard_input) {
+                        panning.button = Some(MouseButton::Left);
+                }
+                panning.last = cursor;
+        }
+        let Some(button) = panning.button else {
+                return;
+        };
+        if !mouse_button_input.pressed(button) {
+                panning.button = None;
+                return;
+        }
+
+        let Ok((mut transform, projection)) = q_camera.get_single_mut() else {
+                return;
+        };
+        // the canvas follows the cursor; screen y runs down, world y up
+        let moved = cursor - panning.last;
+        panning.last = cursor;
+        if moved != Vec2::ZERO {
+                transform.translation += Vec3::new(-moved.x, moved.y, 0.) * projection.scale;
+                target.hold();
+        }
+}
+
+/// Frames every visible element when asked to, with Home or Shift+1, or the toolbar.
+fn fit_all(
+        mut contexts: EguiContexts,
+        keyboard_input: Res<ButtonInput<KeyCode>>,
+        mut requests: EventReader<FitAll>,
+        mut target: ResMut<CameraTarget>,
+        q_window: Query<&Window, With<PrimaryWindow>>,
+        q_shapes: HitQuery,
+) {
+        let shift = keyboard_input.any_pressed([KeyCode::ShiftLeft, KeyCode::ShiftRight]);
+        let shortcut = !contexts.ctx_mut().wants_keyboard_input()
+                && (keyboard_input.just_pressed(KeyCode::Home) || (shift && keyboard_input.just_pressed(KeyCode::Digit1)));
+        if requests.read().count() == 0 && !shortcut {
+                return;
+        }
+
+        let Ok(window) = q_window.get_single() else {
+                return;
+        };
+        if let Some(bounds) = content_bounds(&q_shapes) {
+                target.frame_area(bounds, window);
+        }
+}
+
+/// The area taken up by every visible element, if there are any.
+pub fn content_bounds(q_shapes: &HitQuery) -> Option<Rect> {
+        q_shapes
+                .iter()
+                .filter(|(.., visibility)| visibility.get())
+                .filter_map(|(_, path, _, _, transform, _)| selection::world_bounds(path, transform))
+                .reduce(|a, b| a.union(b))
+}
+
+/// Moves the camera a little further towards its target every frame.
+fn ease_camera(
+        time: Res<Time>,
+        mut target: ResMut<CameraTarget>,
+        mut q_camera: Query<(&mut Transform, &mut OrthographicProjection), With<MainCamera>>,
+) {
+        let Ok((mut transform, mut projection)) = q_camera.get_single_mut() else {
+                return;
+        };
+        // the fraction of the way left that's covered this frame, independent of frame rate
+        let t = 1. - (-EASE_RATE * time.delta_seconds()).exp();
+
+        // zoom evenly in log space, so zooming in feels the same as zooming out
+        let ratio = target.scale / projection.scale;
+        let scale = if (ratio - 1.).abs() < SETTLED_SCALE { target.scale } else { projection.scale * ratio.powf(t) };
+
+        let position = transform.translation.xy();
+        let next = match (target.centre, target.anchor) {
+                (Some(centre), _) => {
+                        if position.distance(centre) < SETTLED_DISTANCE * scale {
+                                if scale == target.scale {
+                                        target.centre = None;
+                                }
+                                centre
+                        } else {
+                                position.lerp(centre, t)
+                        }
+                }
+                // keep the anchor where it is on screen
+                (None, Some(anchor)) => anchor + (position - anchor) * (scale / projection.scale),
+                (None, None) => position,
+        };
+
+        if next != position {
+                transform.translation = next.extend(transform.translation.z);
+        }
+        if projection.scale != scale {
+                projection.scale = scale;
+        }
+}